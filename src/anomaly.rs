@@ -1,124 +1,89 @@
-use std::collections::{HashMap, HashSet};
-use std::sync::mpsc;
-use std::thread;
-
+use crate::clock::{substeps, Clock};
+use crate::distribution::{distribute, distribution_by_name, Distribution};
 use crate::f32_3::dd_f32_3;
-use crate::f64_3::mltply_f64_3;
-use crate::magma_ocean::{magma, petrify, Stone};
-use crate::positions::move_positions;
-use crate::u_modular::modular_offset_in_range;
+use crate::f64_3::{dd_f64_3, mltply_f64_3, nrmlz_f64_3, sbtr_f64_3, vector_length};
+use crate::integrator::{Integration, Integrator, PhaseSpace};
+use crate::mesh_cache::{branch, generated, translation, MeshKey, Placed};
+use crate::octree::{barnes_hut, octree, within, Node};
+use crate::property::{default_value, law_of, Law, PropertyKey, CR, EC, IN0, IN1, IN2, MS, SP};
+use crate::scheduler::{for_each, map, map_range, Scheduler};
+
+use serde::{Deserialize, Serialize};
 
 pub static TS_F64: f64 = 5.391247 * 1e-44;
 pub static LS_F64: f64 = 299792458.0 * 1000000000.0 * 6.1879273537329 * 1e+25;
 
+#[derive(Serialize, Deserialize)]
 pub struct Anomaly {
     pub anomaly: Vec<Anomaly>,
     pub component: Vec<Component>,
     pub force: Vec<Force>,
+    // exact in scenes written before there was an opening angle
+    #[serde(default)]
+    pub theta: f64, // opening angle below which far away children act as one multipole
+    // how the components of this subtree are stepped, the parent's way when None
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integration: Option<Integration>,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Composition {
     pub space: Vec<[f32; 3]>,
-    pub distribution: Vec<fn(Vec<[f32; 3]>) -> Vec<[f32; 3]>>,
+    pub distribution: Vec<Distribution>,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Component {
     pub component: Vec<Component>,
     pub composition: Vec<Composition>,
     pub property: Vec<Property>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>, // mesh file shown instead of generated stones
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Property {
-    pub name: f64,
+    pub name: PropertyKey,
     pub value: f64,
 }
 
+#[derive(Serialize, Deserialize)]
 pub struct Force {
     pub force: Vec<Force>,
     pub range: Vec<f64>,
     pub domain: Vec<Component>,
 }
 
-pub fn interact(anom: &mut Anomaly) {
-    thread::scope(|s| {
-        let mut handles: Vec<thread::ScopedJoinHandle<()>> = vec![];
-        for mut a in anom.anomaly.iter_mut() {
-            let handle = s.spawn(move || interact(&mut a));
-            handles.push(handle);
-        }
-        for h in handles {
-            h.join().unwrap();
-        }
+pub fn interact(anom: &mut Anomaly, scheduler: &Scheduler, time: f64) {
+    for_each(scheduler, &mut anom.anomaly, |a| {
+        interact(a, scheduler, time)
     });
 
-    // access non-overlapping disjoint pairs of vector elements concurrently with social golfer problem solution
-    // since interaction is bidirectional, ordering is irrelevant
-
-    let klen = anom.anomaly.len();
-    let kl2 = klen / 2;
-    let mut sym = false;
-    if klen % 2 == 0 {
-        sym = true;
-    }
-    for i in 1..=kl2 {
-        let mut done: HashSet<usize> = HashSet::new();
-        let mut exit_level = false;
-
-        while !exit_level {
-            let mut pull: HashMap<usize, usize> = HashMap::new();
-            let mut skip: HashSet<usize> = HashSet::new();
-
-            let mut firstopen: usize = 0;
-            let mut instructions_chan: Vec<mpsc::Sender<&mut Anomaly>> = vec![];
-
-            for k in 0..anom.anomaly.len() {
-                let pair = modular_offset_in_range(k as u32, i as u32, 0, (klen - 1) as u32);
-                if !done.contains(&k) && !skip.contains(&k) && !skip.contains(&(pair as usize)) {
-                    pull.insert(k, firstopen);
-                    pull.insert(pair as usize, firstopen);
-                    firstopen += 1;
-                    done.insert(k);
-                    skip.insert(k);
-                    skip.insert(pair as usize);
-                    if sym && i == kl2 {
-                        done.insert(pair as usize);
-                    }
-                }
-            }
-
-            thread::scope(|s| {
-                let mut handles: Vec<thread::ScopedJoinHandle<()>> = vec![];
-                for _ in 0..pull.len() / 2 {
-                    let (tx, rx) = mpsc::channel();
-                    instructions_chan.push(tx);
-                    let handle = s.spawn(move || {
-                        let mut a = rx.recv().unwrap();
-                        let mut b = rx.recv().unwrap();
-                        anomaly_2_interact(&mut a, &mut b);
-                    });
-                    handles.push(handle);
-                }
+    // every child against every other child, each one only receiving the pushes it computes itself
+    // so that the children can be worked on concurrently
 
-                //
+    let pushes = {
+        let groups: Vec<Vec<&Component>> = anom
+            .anomaly
+            .iter()
+            .map(|a| {
+                let mut g = vec![];
+                anomaly_components(a, &mut g);
+                g
+            })
+            .collect();
+        let forces: Vec<&[Force]> = anom.anomaly.iter().map(|a| &a.force[..]).collect();
 
-                for (k, a) in anom.anomaly.iter_mut().enumerate() {
-                    if pull.contains_key(&k) {
-                        instructions_chan[pull[&k]].send(a).unwrap();
-                    }
-                }
-
-                for h in handles {
-                    h.join().unwrap();
-                }
-            });
+        group_pushes(scheduler, &groups, &forces, anom.theta)
+    };
 
-            if done.len() == klen {
-                exit_level = true;
-            }
-        }
-    }
+    let mut pushed: Vec<(&mut Anomaly, Vec<[f64; 3]>)> =
+        anom.anomaly.iter_mut().zip(pushes).collect();
+    for_each(scheduler, &mut pushed, |(a, push)| {
+        push_anomaly(a, push, &mut 0, time)
+    });
 
-    component_interact(anom);
+    component_interact(anom, scheduler, time);
 }
 
 //fn iter_chunks<T, const CHUNK_SIZE: usize>(
@@ -139,187 +104,636 @@ fn array_collect<T, const N: usize>(mut iter: impl Iterator<Item = T>) -> [T; N]
     a.map(|_| iter.next().unwrap())
 }
 
-pub fn anomaly_2_interact(a: &mut Anomaly, b: &mut Anomaly) {
-    for i in a.anomaly.iter_mut() {
-        for j in b.anomaly.iter_mut() {
-            anomaly_2_interact(i, j);
-        }
+pub fn component_interact(anom: &mut Anomaly, scheduler: &Scheduler, time: f64) {
+    let pushes = {
+        let groups: Vec<Vec<&Component>> = anom
+            .component
+            .iter()
+            .map(|c| {
+                let mut g = vec![];
+                component_components(c, &mut g);
+                g
+            })
+            .collect();
+        let forces: Vec<&[Force]> = vec![&anom.force[..]; groups.len()];
+
+        group_pushes(scheduler, &groups, &forces, anom.theta)
+    };
+
+    for (c, push) in anom.component.iter_mut().zip(pushes) {
+        push_components(c, &push, &mut 0, time);
     }
+}
+
+// forces on every component of every group from the components of all other groups,
+// in the order the groups list them
+//
+// forces with a finite range only visit the groups the octree finds inside that range,
+// forces reaching everywhere see far away cells of the tree as a single multipole,
+// theta trades accuracy for speed and 0 visits every pair exactly
+pub fn group_pushes(
+    scheduler: &Scheduler,
+    groups: &[Vec<&Component>],
+    forces: &[&[Force]],
+    theta: f64,
+) -> Vec<Vec<[f64; 3]>> {
+    let mut centers = vec![];
+    let mut extents = vec![];
+    for g in groups {
+        let (center, extent) = group_center(g);
+        centers.push(center);
+        extents.push(extent);
+    }
+    let max_extent = extents.iter().cloned().fold(0.0, f64::max);
+
+    let tree = octree(&centers);
 
-    for df in &a.force {
-        for i in 0..a.component.len() {
-            for j in 0..b.component.len() {
-                component_2_interact(df, &mut a.component[i], &mut b.component[j]);
+    let mut names = vec![];
+    for f in forces {
+        for n in force_nodes(f) {
+            if range_cutoff(n) == f64::MAX {
+                for d in &n.domain {
+                    for p in &d.property {
+                        if !names.contains(&p.name) {
+                            names.push(p.name);
+                        }
+                    }
+                }
             }
         }
     }
+    let moments = map_range(scheduler, tree.nodes.len(), |n| {
+        node_moments(&tree.nodes[n], groups, &names)
+    });
+
+    map_range(scheduler, groups.len(), |i| {
+        let mut push = vec![[0.0, 0.0, 0.0]; groups[i].len()];
+        let mut add_group = |f: &Force, j: usize| {
+            for (k, a) in groups[i].iter().enumerate() {
+                for b in &groups[j] {
+                    push[k] = dd_f64_3(push[k], domain_force(f, a, b));
+                }
+            }
+        };
+
+        let (near, far) = barnes_hut(&tree, i, theta);
+        let mut far_nodes = vec![];
+
+        for f in force_nodes(forces[i]) {
+            if f.domain.is_empty() {
+                continue;
+            }
+
+            let cutoff = range_cutoff(f);
+            if cutoff == f64::MAX {
+                for j in &near {
+                    add_group(f, *j);
+                }
+                far_nodes.push(f);
+            } else {
+                for j in within(&tree, centers[i], cutoff + extents[i] + max_extent) {
+                    if j != i {
+                        add_group(f, j);
+                    }
+                }
+            }
+        }
+
+        for f in far_nodes {
+            for (k, a) in groups[i].iter().enumerate() {
+                for n in &far {
+                    for m in &moments[*n] {
+                        push[k] = dd_f64_3(push[k], domain_force(f, a, m));
+                    }
+                }
+            }
+        }
+
+        push
+    })
 }
 
-pub fn component_interact(_anom: &mut Anomaly) {
-    for df in &_anom.force {
-        for i in 0.._anom.component.len() {
-            for j in 0.._anom.component.len() {
-                let (e, f) = if i < j {
-                    let (left, right) = _anom.component.split_at_mut(j);
-                    (&mut left[i], &mut right[0])
-                } else if i == j {
-                    continue;
-                } else {
-                    let (left, right) = _anom.component.split_at_mut(i);
-                    (&mut right[0], &mut left[j])
-                };
-                component_2_interact(df, e, f);
+// a point component per property, carrying the sum of that property over a cell
+// at the center weighted by the size of each contribution
+pub fn node_moments(
+    node: &Node,
+    groups: &[Vec<&Component>],
+    names: &[PropertyKey],
+) -> Vec<Component> {
+    let mut moments = vec![];
+    for name in names {
+        let mut total = 0.0;
+        let mut weight = 0.0;
+        let mut center = [0.0, 0.0, 0.0];
+        for b in &node.bodies {
+            for c in &groups[*b] {
+                if let (Some(v), Some(p)) = (component_property(c, *name), component_center(c)) {
+                    total += v;
+                    weight += v.abs();
+                    center = dd_f64_3(center, mltply_f64_3(p, v.abs()));
+                }
             }
         }
+
+        if weight == 0.0 {
+            continue;
+        }
+        let center = mltply_f64_3(center, 1.0 / weight);
+
+        moments.push(Component {
+            component: vec![],
+            composition: vec![Composition {
+                space: vec![[center[0] as f32, center[1] as f32, center[2] as f32]],
+                distribution: vec![],
+            }],
+            property: vec![Property {
+                name: *name,
+                value: total,
+            }],
+            asset: None,
+        });
     }
+    moments
 }
 
-pub fn component_2_interact(df: &Force, a: &mut Component, b: &mut Component) {
-    for i in a.component.iter_mut() {
-        for j in b.component.iter_mut() {
-            component_2_interact(df, i, j);
-        }
+pub fn group_center(group: &[&Component]) -> ([f64; 3], f64) {
+    let centers: Vec<[f64; 3]> = group.iter().filter_map(|c| component_center(c)).collect();
+    if centers.is_empty() {
+        return ([0.0, 0.0, 0.0], 0.0);
+    }
+
+    let mut center = [0.0, 0.0, 0.0];
+    for c in &centers {
+        center = dd_f64_3(center, *c);
     }
+    center = mltply_f64_3(center, 1.0 / centers.len() as f64);
+
+    let extent = centers
+        .iter()
+        .map(|c| vector_length(sbtr_f64_3(*c, center)))
+        .fold(0.0, f64::max);
 
-    force_apply(df, a, b);
+    (center, extent)
 }
 
-pub fn force_apply(_f: &Force, a: &mut Component, b: &mut Component) {
-    set_inertia([0.0, 0.0, 0.0], a);
+// a force and all of its sub forces
+pub fn force_nodes(forces: &[Force]) -> Vec<&Force> {
+    let mut nodes = vec![];
+    for f in forces {
+        nodes.push(f);
+        nodes.append(&mut force_nodes(&f.force));
+    }
+    nodes
+}
 
-    set_inertia([0.0, 0.0, 0.0], b);
+pub fn range_cutoff(f: &Force) -> f64 {
+    f.range.iter().cloned().fold(f64::MAX, f64::min)
 }
 
-pub fn progress(anom: &mut Anomaly, time: f64) {
-    thread::scope(|s| {
-        let mut handles: Vec<thread::ScopedJoinHandle<()>> = vec![];
-        for mut a in anom.anomaly.iter_mut() {
-            let handle = s.spawn(move || {
-                interact(&mut a);
-                progress(&mut a, time);
-            });
-            handles.push(handle);
-        }
-        for h in handles {
-            h.join().unwrap();
+// components in the same order as anomaly_phase lists them
+pub fn anomaly_components<'a>(anom: &'a Anomaly, g: &mut Vec<&'a Component>) {
+    for a in &anom.anomaly {
+        anomaly_components(a, g);
+    }
+    for c in &anom.component {
+        component_components(c, g);
+    }
+}
+
+pub fn component_components<'a>(component: &'a Component, g: &mut Vec<&'a Component>) {
+    for c in &component.component {
+        component_components(c, g);
+    }
+    g.push(component);
+}
+
+pub fn push_anomaly(anom: &mut Anomaly, pushes: &[[f64; 3]], k: &mut usize, time: f64) {
+    for a in anom.anomaly.iter_mut() {
+        push_anomaly(a, pushes, k, time);
+    }
+    for c in anom.component.iter_mut() {
+        push_components(c, pushes, k, time);
+    }
+}
+
+pub fn push_components(component: &mut Component, pushes: &[[f64; 3]], k: &mut usize, time: f64) {
+    for c in component.component.iter_mut() {
+        push_components(c, pushes, k, time);
+    }
+    push_component(pushes[*k], component, time);
+    *k += 1;
+}
+
+pub fn push_component(push: [f64; 3], c: &mut Component, time: f64) {
+    // the impulse of the force over time, turned into a change of inertia by the component's mass
+    if let Some(m) = component_property(c, MS).filter(|m| *m > 0.0) {
+        add_inertia(mltply_f64_3(push, time / m), c);
+    }
+}
+
+// force exerted on a by b, summed over f and all of its sub forces
+pub fn force_of(f: &Force, a: &Component, b: &Component) -> [f64; 3] {
+    let mut push = domain_force(f, a, b);
+    for g in &f.force {
+        push = dd_f64_3(push, force_of(g, a, b));
+    }
+    push
+}
+
+// force exerted on a by b through the domain of f alone, leaving out its sub forces
+pub fn domain_force(f: &Force, a: &Component, b: &Component) -> [f64; 3] {
+    let mut push = [0.0, 0.0, 0.0];
+
+    let (ca, cb) = match (component_center(a), component_center(b)) {
+        (Some(ca), Some(cb)) => (ca, cb),
+        _ => return push,
+    };
+
+    let diff = sbtr_f64_3(ca, cb);
+    let distance = vector_length(diff);
+    if distance == 0.0 || !within_range(f, distance) {
+        return push;
+    }
+
+    let direction = nrmlz_f64_3(diff);
+    for d in &f.domain {
+        for p in &d.property {
+            // positive strength pushes a away from b, negative pulls it closer
+            let strength = force_law(p.name, p.value, a, b);
+            push = dd_f64_3(push, mltply_f64_3(direction, strength / distance.powi(2)));
         }
-    });
+    }
 
-    for i in 0..anom.anomaly.len() {
-        for j in 0..anom.anomaly.len() {
-            let (e, f) = if i < j {
-                let (left, right) = anom.anomaly.split_at_mut(j);
-                (&mut left[i], &mut right[0])
-            } else if i == j {
-                continue;
+    push
+}
+
+pub fn force_law(name: PropertyKey, coupling: f64, a: &Component, b: &Component) -> f64 {
+    let pa = component_property(a, name);
+    let pb = component_property(b, name);
+
+    let (pa, pb) = match (pa, pb) {
+        (Some(pa), Some(pb)) => (pa, pb),
+        _ => return 0.0,
+    };
+
+    match law_of(name) {
+        Law::LikeRepels => coupling * pa * pb,
+        Law::Attracts => -coupling * pa * pb,
+        Law::ColorCharge => {
+            if pa == pb {
+                coupling
             } else {
-                let (left, right) = anom.anomaly.split_at_mut(i);
-                (&mut right[0], &mut left[j])
-            };
-            anomaly_2_interact(e, f);
+                -coupling
+            }
         }
+        Law::Inert => 0.0,
     }
+}
 
-    let steps = (time / TS_F64) as u64;
-    for _ in 0..steps {
-        thread::scope(|s| {
-            let mut handles: Vec<thread::ScopedJoinHandle<()>> = vec![];
-            for mut c in anom.component.iter_mut() {
-                let handle = s.spawn(move || {
-                    component_progress(&mut c, TS_F64);
-                });
-                handles.push(handle);
-            }
-            for h in handles {
-                h.join().unwrap();
+pub fn within_range(f: &Force, distance: f64) -> bool {
+    f.range.iter().all(|r| distance <= *r)
+}
+
+pub fn component_center(component: &Component) -> Option<[f64; 3]> {
+    let mut sum = [0.0, 0.0, 0.0];
+    let mut count = 0;
+    for c in &component.composition {
+        for s in &c.space {
+            sum = dd_f64_3(sum, [s[0] as f64, s[1] as f64, s[2] as f64]);
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    Some(mltply_f64_3(sum, 1.0 / count as f64))
+}
+
+pub fn progress(anom: &mut Anomaly, scheduler: &Scheduler, clock: &mut Clock, time: f64) {
+    for _ in 0..substeps(clock, time) {
+        integrate(anom, scheduler, clock.integration, clock.timestep);
+    }
+}
+
+// one step of the whole tree, every subtree by its own integrator and the rest by the one
+// given; where they differ the parts stepped one way are moved one after another, each
+// against the others where the previous ones left them
+pub fn integrate(anom: &mut Anomaly, scheduler: &Scheduler, integration: Integration, time: f64) {
+    let mut phase = PhaseSpace {
+        position: vec![],
+        inertia: vec![],
+    };
+    anomaly_phase(anom, &mut phase);
+
+    let mut ways = vec![];
+    anomaly_integrations(anom, anom.integration.unwrap_or(integration), &mut ways);
+    let mut kinds = ways.clone();
+    kinds.sort_by_key(|k| *k as u8);
+    kinds.dedup();
+
+    for kind in kinds {
+        let part: Vec<usize> = (0..ways.len()).filter(|i| ways[*i] == kind).collect();
+        let mut sub = PhaseSpace {
+            position: part.iter().map(|i| phase.position[*i]).collect(),
+            inertia: part.iter().map(|i| phase.inertia[*i]).collect(),
+        };
+        kind.integrate(&mut sub, time, &mut |p| {
+            let mut whole = phase.clone();
+            for (k, i) in part.iter().enumerate() {
+                whole.position[*i] = p.position[k];
+                whole.inertia[*i] = p.inertia[k];
             }
+            let all = accelerations(anom, scheduler, &whole);
+            part.iter().map(|i| all[*i]).collect()
         });
+        for (k, i) in part.iter().enumerate() {
+            phase.position[*i] = sub.position[k];
+            phase.inertia[*i] = sub.inertia[k];
+        }
     }
+
+    set_anomaly_phase(anom, &phase, &mut 0);
 }
 
-pub fn component_property(component: &mut Component, name: f64) -> f64 {
-    let prop: Vec<&mut Property> = component
-        .property
-        .iter_mut()
-        .filter(|c| c.name == name)
-        .collect();
+// the integrator of every entry of the tree's phase space, in the order anomaly_phase lists them
+pub fn anomaly_integrations(anom: &Anomaly, inherited: Integration, ways: &mut Vec<Integration>) {
+    for a in &anom.anomaly {
+        anomaly_integrations(a, a.integration.unwrap_or(inherited), ways);
+    }
+    let mut phase = PhaseSpace {
+        position: vec![],
+        inertia: vec![],
+    };
+    for c in &anom.component {
+        component_phase(c, &mut phase);
+    }
+    ways.extend(std::iter::repeat_n(inherited, phase.position.len()));
+}
+
+pub fn accelerations(
+    anom: &mut Anomaly,
+    scheduler: &Scheduler,
+    phase: &PhaseSpace,
+) -> Vec<[f64; 3]> {
+    // starting from rest, a unit of time of interaction leaves every inertia equal to its acceleration
+    let at_rest = PhaseSpace {
+        position: phase.position.clone(),
+        inertia: vec![[0.0, 0.0, 0.0]; phase.inertia.len()],
+    };
+    set_anomaly_phase(anom, &at_rest, &mut 0);
+
+    interact(anom, scheduler, 1.0);
+
+    let mut accelerated = PhaseSpace {
+        position: vec![],
+        inertia: vec![],
+    };
+    anomaly_phase(anom, &mut accelerated);
+
+    accelerated.inertia
+}
+
+pub fn anomaly_phase(anom: &Anomaly, phase: &mut PhaseSpace) {
+    for a in &anom.anomaly {
+        anomaly_phase(a, phase);
+    }
+    for c in &anom.component {
+        component_phase(c, phase);
+    }
+}
+
+pub fn component_phase(component: &Component, phase: &mut PhaseSpace) {
+    for c in &component.component {
+        component_phase(c, phase);
+    }
 
-    return prop[0].value;
+    phase
+        .position
+        .push(component_center(component).unwrap_or([0.0, 0.0, 0.0]));
+    phase.inertia.push(component_inertia(component));
 }
 
-pub fn set_component_property(n: f64, s: f64, component: &mut Component) {
-    for p in component.property.iter_mut() {
-        if n == p.name {
-            *p = Property { name: n, value: s };
+pub fn set_anomaly_phase(anom: &mut Anomaly, phase: &PhaseSpace, k: &mut usize) {
+    for a in anom.anomaly.iter_mut() {
+        set_anomaly_phase(a, phase, k);
+    }
+    for c in anom.component.iter_mut() {
+        set_component_phase(c, phase, k);
+    }
+}
+
+pub fn set_component_phase(component: &mut Component, phase: &PhaseSpace, k: &mut usize) {
+    for c in component.component.iter_mut() {
+        set_component_phase(c, phase, k);
+    }
+
+    if let Some(center) = component_center(component) {
+        let mov0 = sbtr_f64_3(phase.position[*k], center);
+        for c in &mut component.composition {
+            for s in c.space.iter_mut() {
+                *s = dd_f32_3(*s, [mov0[0] as f32, mov0[1] as f32, mov0[2] as f32]);
+            }
         }
     }
+    set_inertia(phase.inertia[*k], component);
+
+    *k += 1;
 }
 
-pub fn set_inertia(in0: [f64; 3], c: &mut Component) {
-    set_component_property(IN0, in0[0], c);
-    set_component_property(IN1, in0[1], c);
-    set_component_property(IN2, in0[2], c);
+pub fn component_inertia(component: &Component) -> [f64; 3] {
+    [
+        component_property(component, IN0).unwrap_or(0.0),
+        component_property(component, IN1).unwrap_or(0.0),
+        component_property(component, IN2).unwrap_or(0.0),
+    ]
 }
 
-pub fn component_progress(component: &mut Component, time: f64) {
-    for mut c in component.component.iter_mut() {
-        component_progress(&mut c, time);
+pub fn kinetic_energy(anom: &Anomaly) -> f64 {
+    let mut k = 0.0;
+    for a in &anom.anomaly {
+        k += kinetic_energy(a);
+    }
+    for c in &anom.component {
+        k += component_kinetic_energy(c);
     }
+    k
+}
 
-    let inertia_0 = component_property(component, IN0);
-    let inertia_1 = component_property(component, IN1);
-    let inertia_2 = component_property(component, IN2);
+pub fn component_kinetic_energy(component: &Component) -> f64 {
+    let mut k = 0.0;
+    for c in &component.component {
+        k += component_kinetic_energy(c);
+    }
 
-    for c in &mut component.composition {
-        for s in c.space.iter_mut() {
-            let mov0 = mltply_f64_3([inertia_0, inertia_1, inertia_2], TS_F64);
-            *s = dd_f32_3(*s, [mov0[0] as f32, mov0[1] as f32, mov0[2] as f32]);
+    let m = component_property(component, MS).unwrap_or(0.0);
+    let v = vector_length(component_inertia(component));
+    k + 0.5 * m * v.powi(2)
+}
+
+// the potential of what interact does: every child against every other child under the
+// receiver's forces, all the components of a child grouped together, and the components under
+// the anomaly's own forces; exact, like interact with a theta of 0
+pub fn potential_energy(anom: &Anomaly) -> f64 {
+    let mut u = 0.0;
+    for a in &anom.anomaly {
+        u += potential_energy(a);
+    }
+
+    let groups: Vec<Vec<&Component>> = anom
+        .anomaly
+        .iter()
+        .map(|a| {
+            let mut g = vec![];
+            anomaly_components(a, &mut g);
+            g
+        })
+        .collect();
+    let forces: Vec<&[Force]> = anom.anomaly.iter().map(|a| &a.force[..]).collect();
+    u += group_potential(&groups, &forces);
+
+    let groups: Vec<Vec<&Component>> = anom
+        .component
+        .iter()
+        .map(|c| {
+            let mut g = vec![];
+            component_components(c, &mut g);
+            g
+        })
+        .collect();
+    let forces: Vec<&[Force]> = vec![&anom.force[..]; groups.len()];
+    u + group_potential(&groups, &forces)
+}
+
+// the counterpart of group_pushes; every group feels the others through its own forces, so
+// each pair is counted from both sides at half its potential
+pub fn group_potential(groups: &[Vec<&Component>], forces: &[&[Force]]) -> f64 {
+    let mut u = 0.0;
+    for i in 0..groups.len() {
+        for j in 0..groups.len() {
+            if i == j {
+                continue;
+            }
+            for f in forces[i] {
+                for a in &groups[i] {
+                    for b in &groups[j] {
+                        u += 0.5 * potential_of(f, a, b);
+                    }
+                }
+            }
         }
     }
+    u
 }
 
-pub fn view(anom: &mut Anomaly) -> Vec<Stone> {
-    let mut ret: Vec<Stone> = vec![];
-    let mut rs: Vec<mpsc::Receiver<Vec<Stone>>> = vec![];
+// potential energy of a and b under f and its sub forces, the counterpart of force_of
+pub fn potential_of(f: &Force, a: &Component, b: &Component) -> f64 {
+    let mut u = 0.0;
+    for g in &f.force {
+        u += potential_of(g, a, b);
+    }
+
+    let (ca, cb) = match (component_center(a), component_center(b)) {
+        (Some(ca), Some(cb)) => (ca, cb),
+        _ => return u,
+    };
 
-    thread::scope(|s| {
-        for mut a in anom.anomaly.iter_mut() {
-            let (tx, rx) = mpsc::channel();
-            rs.push(rx);
-            s.spawn(move || {
-                let k = view(&mut a);
-                tx.send(k).unwrap();
-            });
+    let distance = vector_length(sbtr_f64_3(ca, cb));
+    if distance == 0.0 || !within_range(f, distance) {
+        return u;
+    }
+
+    for d in &f.domain {
+        for p in &d.property {
+            u += force_law(p.name, p.value, a, b) / distance;
         }
-    });
+    }
 
-    for c in anom.component.iter_mut() {
-        ret.append(&mut component_view(c));
+    u
+}
+
+// the component's own value of a property, or the property's default when it does not carry one
+pub fn component_property(component: &Component, name: PropertyKey) -> Option<f64> {
+    component
+        .property
+        .iter()
+        .find(|c| c.name == name)
+        .map(|c| c.value)
+        .or_else(|| default_value(name))
+}
+
+pub fn set_component_property(n: PropertyKey, s: f64, component: &mut Component) {
+    match component.property.iter_mut().find(|p| p.name == n) {
+        Some(p) => p.value = s,
+        None => component.property.push(Property { name: n, value: s }),
+    }
+}
+
+pub fn set_inertia(in0: [f64; 3], c: &mut Component) {
+    set_component_property(IN0, in0[0], c);
+    set_component_property(IN1, in0[1], c);
+    set_component_property(IN2, in0[2], c);
+}
+
+pub fn add_inertia(in0: [f64; 3], c: &mut Component) {
+    let inertia = component_inertia(c);
+    set_inertia(dd_f64_3(inertia, in0), c);
+}
+
+// which of the variants every stone shows follows from where it sits in the tree, however the
+// children get scheduled, so shapes stay the same from frame to frame
+pub fn view(anom: &mut Anomaly, scheduler: &Scheduler) -> Vec<Placed> {
+    anomaly_view(anom, scheduler, 0)
+}
+
+fn anomaly_view(anom: &mut Anomaly, scheduler: &Scheduler, path: u64) -> Vec<Placed> {
+    let mut ret: Vec<Placed> = vec![];
+
+    let mut branched: Vec<(&mut Anomaly, u64)> = anom
+        .anomaly
+        .iter_mut()
+        .enumerate()
+        .map(|(i, a)| (a, branch(path, i as u64)))
+        .collect();
+
+    for (i, c) in anom.component.iter_mut().enumerate() {
+        ret.append(&mut component_view(c, branch(!path, i as u64)));
     }
 
-    for r in rs {
-        let mut rec = r.recv().unwrap();
+    let rs = map(scheduler, &mut branched, |(a, path)| {
+        anomaly_view(a, scheduler, *path)
+    });
+
+    for mut rec in rs {
         ret.append(&mut rec);
     }
 
     ret
 }
 
-pub fn component_view(component: &mut Component) -> Vec<Stone> {
-    let mut ret: Vec<Stone> = vec![];
+pub fn component_view(component: &mut Component, path: u64) -> Vec<Placed> {
+    let mut ret: Vec<Placed> = vec![];
 
-    for c in component.component.iter_mut() {
-        ret.append(&mut component_view(c));
+    for (i, c) in component.component.iter_mut().enumerate() {
+        ret.append(&mut component_view(c, branch(path, i as u64)));
     }
 
-    let size = component_property(component, MS);
+    let size = component_property(component, MS).unwrap_or(0.0);
 
+    let mut k = 0;
     for c in &component.composition {
         for d in &c.distribution {
-            for v in &d(c.space.clone()) {
-                let mut s = petrify(magma(2, size as f32));
-                move_positions(&mut s.positions, *v);
-                ret.push(s);
+            for v in &distribute(d, c.space.clone()) {
+                let mesh = match &component.asset {
+                    Some(path) => MeshKey::Asset(path.clone()),
+                    None => generated(size, branch(!path, k)),
+                };
+                ret.push(Placed {
+                    mesh,
+                    transform: translation(*v),
+                });
+                k += 1;
             }
         }
     }
@@ -337,25 +751,28 @@ pub fn particle(position: [f32; 3], properties: Vec<Property>) -> Anomaly {
             component: vec![],
             composition: vec![Composition {
                 space: vec![position],
-                distribution: vec![particular],
+                distribution: vec![distribution_by_name("particular").unwrap()],
             }],
             property: properties,
+            asset: None,
         }],
         force: force_base().force,
+        theta: 0.5,
+        integration: None,
     };
 
     anom
 }
 
-static EC: f64 = 313.0;
-static SP: f64 = 591.0;
-static MS: f64 = 343.0;
-static CR: f64 = 0.10;
-static IN0: f64 = 141.0;
-static IN1: f64 = 141.1;
-static IN2: f64 = 141.2;
 static QMS: [f64; 6] = [2.2, 4.7, 1.28, 96.0, 173.1, 4.18];
 
+// a massless component showing a mesh file at the position, carried along but pulling on nothing
+pub fn asset(position: [f32; 3], path: &str) -> Anomaly {
+    let mut anom = particle(position, vec![]);
+    anom.component[0].asset = Some(path.to_string());
+    anom
+}
+
 pub fn e(position: [f32; 3], inertia: [f64; 3], clock: bool) -> Anomaly {
     let sp = if clock { 0.5 } else { -0.5 };
     particle(
@@ -435,10 +852,6 @@ pub fn q(
     )
 }
 
-pub fn particular(coordinates: Vec<[f32; 3]>) -> Vec<[f32; 3]> {
-    return coordinates;
-}
-
 pub fn force_base() -> Force {
     return Force {
         force: vec![
@@ -452,6 +865,7 @@ pub fn force_base() -> Force {
                         name: CR,
                         value: 1.0,
                     }],
+                    asset: None,
                 }],
             },
             Force {
@@ -464,6 +878,7 @@ pub fn force_base() -> Force {
                         name: EC,
                         value: 1.0 / 137.0,
                     }],
+                    asset: None,
                 }],
             },
             Force {
@@ -478,6 +893,7 @@ pub fn force_base() -> Force {
                                 name: MS,
                                 value: 1e-13,
                             }],
+                            asset: None,
                         }],
                     },
                     Force {
@@ -490,6 +906,7 @@ pub fn force_base() -> Force {
                                 name: SP,
                                 value: 1e-13,
                             }],
+                            asset: None,
                         }],
                     },
                 ],
@@ -506,6 +923,7 @@ pub fn force_base() -> Force {
                         name: MS,
                         value: 1e-41,
                     }],
+                    asset: None,
                 }],
             },
         ],
@@ -513,3 +931,292 @@ pub fn force_base() -> Force {
         domain: vec![],
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::scheduler;
+
+    // relative change of the energy over a number of integration steps
+    fn energy_drift(
+        anom: &mut Anomaly,
+        scheduler: &Scheduler,
+        integration: Integration,
+        time: f64,
+        steps: u64,
+    ) -> f64 {
+        let energy = |a: &Anomaly| kinetic_energy(a) + potential_energy(a);
+        let e0 = energy(anom);
+        for _ in 0..steps {
+            integrate(anom, scheduler, integration, time);
+        }
+        let e1 = energy(anom);
+
+        (e1 - e0) / e0.abs().max(f64::MIN_POSITIVE)
+    }
+
+    // pulls every component carrying mass towards every other, a coupling of one
+    fn gravity() -> Force {
+        Force {
+            force: vec![],
+            range: vec![],
+            domain: vec![Component {
+                component: vec![],
+                composition: vec![],
+                property: vec![Property {
+                    name: MS,
+                    value: 1.0,
+                }],
+                asset: None,
+            }],
+        }
+    }
+
+    fn body(position: [f32; 3], inertia: [f64; 3]) -> Component {
+        Component {
+            component: vec![],
+            composition: vec![Composition {
+                space: vec![position],
+                distribution: vec![],
+            }],
+            property: vec![
+                Property {
+                    name: MS,
+                    value: 1.0,
+                },
+                Property {
+                    name: IN0,
+                    value: inertia[0],
+                },
+                Property {
+                    name: IN1,
+                    value: inertia[1],
+                },
+                Property {
+                    name: IN2,
+                    value: inertia[2],
+                },
+            ],
+            asset: None,
+        }
+    }
+
+    // a coupling of one between masses no further apart than range
+    fn short_gravity(range: f64) -> Force {
+        Force {
+            range: vec![range],
+            ..gravity()
+        }
+    }
+
+    // two equal masses two apart, circling their middle once every 4 pi
+    fn orbit() -> Anomaly {
+        Anomaly {
+            anomaly: vec![],
+            component: vec![
+                body([-1.0, 0.0, 0.0], [0.0, 0.0, -0.5]),
+                body([1.0, 0.0, 0.0], [0.0, 0.0, 0.5]),
+            ],
+            force: vec![gravity()],
+            theta: 0.0,
+            integration: None,
+        }
+    }
+
+    // two orbits in steps of a twentieth
+    fn orbit_drift(integration: Integration) -> f64 {
+        energy_drift(&mut orbit(), &scheduler(1), integration, 0.05, 500).abs()
+    }
+
+    #[test]
+    fn symplectic_integrators_keep_the_orbit_energy() {
+        assert!(orbit_drift(Integration::SemiImplicitEuler) < 1e-3);
+        assert!(orbit_drift(Integration::VelocityVerlet) < 1e-4);
+        assert!(orbit_drift(Integration::RungeKutta4) < 1e-4);
+    }
+
+    // the same orbit with one body a level further down, reached only through its parent
+    #[test]
+    fn nested_bodies_keep_the_orbit_energy() {
+        let mut nested = Anomaly {
+            anomaly: vec![
+                Anomaly {
+                    anomaly: vec![Anomaly {
+                        anomaly: vec![],
+                        component: vec![body([-1.0, 0.0, 0.0], [0.0, 0.0, -0.5])],
+                        force: vec![gravity()],
+                        theta: 0.0,
+                        integration: None,
+                    }],
+                    component: vec![],
+                    force: vec![gravity()],
+                    theta: 0.0,
+                    integration: None,
+                },
+                Anomaly {
+                    anomaly: vec![],
+                    component: vec![body([1.0, 0.0, 0.0], [0.0, 0.0, 0.5])],
+                    force: vec![gravity()],
+                    theta: 0.0,
+                    integration: None,
+                },
+            ],
+            component: vec![],
+            force: vec![],
+            theta: 0.0,
+            integration: None,
+        };
+        assert!((potential_energy(&nested) - potential_energy(&orbit())).abs() < 1e-9);
+        let drift = energy_drift(
+            &mut nested,
+            &scheduler(1),
+            Integration::VelocityVerlet,
+            0.05,
+            500,
+        );
+        assert!(drift.abs() < 1e-4);
+    }
+
+    #[test]
+    fn explicit_euler_gains_energy_on_the_orbit() {
+        assert!(orbit_drift(Integration::ExplicitEuler) > 1e-1);
+    }
+
+    // the clock's integrator is only where the tree names none
+    #[test]
+    fn an_anomaly_steps_its_own_way() {
+        let mut euler = orbit();
+        euler.integration = Some(Integration::ExplicitEuler);
+        let drift = energy_drift(
+            &mut euler,
+            &scheduler(1),
+            Integration::VelocityVerlet,
+            0.05,
+            500,
+        );
+        assert!(drift > 1e-1);
+
+        let mut verlet = orbit();
+        verlet.integration = Some(Integration::VelocityVerlet);
+        let drift = energy_drift(
+            &mut verlet,
+            &scheduler(1),
+            Integration::ExplicitEuler,
+            0.05,
+            500,
+        );
+        assert!(drift.abs() < 1e-4);
+    }
+
+    // each body of the orbit in a subtree of its own, one of them stepped differently
+    #[test]
+    fn subtrees_step_their_own_way() {
+        let single = |position, inertia, integration| Anomaly {
+            anomaly: vec![],
+            component: vec![body(position, inertia)],
+            force: vec![gravity()],
+            theta: 0.0,
+            integration,
+        };
+        let mut mixed = Anomaly {
+            anomaly: vec![
+                single(
+                    [-1.0, 0.0, 0.0],
+                    [0.0, 0.0, -0.5],
+                    Some(Integration::RungeKutta4),
+                ),
+                single([1.0, 0.0, 0.0], [0.0, 0.0, 0.5], None),
+            ],
+            component: vec![],
+            force: vec![],
+            theta: 0.0,
+            integration: None,
+        };
+
+        let mut ways = vec![];
+        anomaly_integrations(&mixed, Integration::VelocityVerlet, &mut ways);
+        assert_eq!(
+            ways,
+            vec![Integration::RungeKutta4, Integration::VelocityVerlet]
+        );
+
+        let drift = energy_drift(
+            &mut mixed,
+            &scheduler(1),
+            Integration::VelocityVerlet,
+            0.05,
+            500,
+        );
+        assert!(drift.abs() < 1e-4);
+    }
+
+    #[test]
+    fn barnes_hut_forces_stay_close_to_exact_ones() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(5);
+        let clumps: Vec<[f32; 3]> = (0..6)
+            .map(|_| [0, 1, 2].map(|_| rng.gen_range(-50.0..50.0)))
+            .collect();
+        let bodies: Vec<Component> = (0..300)
+            .map(|i| {
+                let offset = [0, 1, 2].map(|_| rng.gen_range(-3.0..3.0));
+                body(dd_f32_3(clumps[i % clumps.len()], offset), [0.0; 3])
+            })
+            .collect();
+        let groups: Vec<Vec<&Component>> = bodies.iter().map(|b| vec![b]).collect();
+        let forces = vec![gravity()];
+        let forces: Vec<&[Force]> = vec![&forces[..]; groups.len()];
+        let scheduler = scheduler(1);
+
+        let exact = group_pushes(&scheduler, &groups, &forces, 0.0);
+        let approximate = group_pushes(&scheduler, &groups, &forces, 0.5);
+
+        // the approximation has to group something to be tested at all
+        let centers: Vec<[f64; 3]> = groups.iter().map(|g| group_center(g).0).collect();
+        let tree = octree(&centers);
+        assert!((0..groups.len()).all(|i| !barnes_hut(&tree, i, 0.5).1.is_empty()));
+
+        let mut worst: f64 = 0.0;
+        for (e, a) in exact.iter().zip(&approximate) {
+            let error = vector_length(sbtr_f64_3(a[0], e[0])) / vector_length(e[0]);
+            worst = worst.max(error);
+        }
+        assert!(worst < 2e-2, "relative error {worst}");
+    }
+
+    #[test]
+    fn a_finite_range_reaches_only_bodies_within_it() {
+        let bodies = [
+            body([0.0, 0.0, 0.0], [0.0; 3]),
+            body([1.5, 0.0, 0.0], [0.0; 3]),
+            body([10.0, 0.0, 0.0], [0.0; 3]),
+            // a group centered far away with one member in reach
+            body([2.5, 0.0, 0.0], [0.0; 3]),
+            body([50.0, 0.0, 0.0], [0.0; 3]),
+        ];
+        let groups: Vec<Vec<&Component>> = vec![
+            vec![&bodies[0]],
+            vec![&bodies[1]],
+            vec![&bodies[2]],
+            vec![&bodies[3], &bodies[4]],
+        ];
+        let forces = vec![short_gravity(3.0)];
+        let forces: Vec<&[Force]> = vec![&forces[..]; groups.len()];
+
+        let pushes = group_pushes(&scheduler(1), &groups, &forces, 0.5);
+
+        let f = &forces[0][0];
+        let expected = dd_f64_3(
+            domain_force(f, &bodies[0], &bodies[1]),
+            domain_force(f, &bodies[0], &bodies[3]),
+        );
+        assert!(vector_length(sbtr_f64_3(pushes[0][0], expected)) < 1e-12);
+        assert!(vector_length(expected) > 0.0);
+        // nothing lies within reach of the lone body at ten
+        assert_eq!(pushes[2][0], [0.0; 3]);
+        // the far member of the wide group is out of everyone's reach
+        assert_eq!(pushes[3][1], [0.0; 3]);
+    }
+}
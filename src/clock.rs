@@ -0,0 +1,104 @@
+use crate::anomaly::TS_F64;
+use crate::integrator::Integration;
+
+// turns render time into a whole number of fixed simulation steps,
+// carrying the remainder over to the next call
+
+#[derive(Debug, Clone)]
+pub struct Clock {
+    pub timestep: f64,            // simulated time of a single sub step
+    pub max_substeps: u32, // most sub steps taken for one call, the rest of the time is dropped
+    pub time_scale: f64,   // simulated time per unit of render time
+    pub accumulated: f64,  // simulated time owed but not yet stepped
+    pub elapsed: f64,      // simulated time stepped so far
+    pub dropped: f64,      // simulated time skipped because of the sub step cap
+    pub integration: Integration, // how sub steps are taken where the tree names no other way
+}
+
+pub fn clock(timestep: f64, max_substeps: u32, time_scale: f64) -> Clock {
+    Clock {
+        timestep,
+        max_substeps,
+        time_scale,
+        accumulated: 0.0,
+        elapsed: 0.0,
+        dropped: 0.0,
+        integration: Integration::SemiImplicitEuler,
+    }
+}
+
+// render_time of wall clock time advances the simulation by planck_units steps of TS_F64;
+// None for a render time that is not positive, which gives no time scale
+pub fn planck_clock(render_time: f64, planck_units: f64, max_substeps: u32) -> Option<Clock> {
+    if render_time.is_nan() || render_time <= 0.0 {
+        return None;
+    }
+    Some(clock(
+        TS_F64,
+        max_substeps,
+        planck_units * TS_F64 / render_time,
+    ))
+}
+
+pub fn substeps(clock: &mut Clock, render_time: f64) -> u32 {
+    clock.accumulated += render_time * clock.time_scale;
+
+    // the small allowance keeps rounding from turning an exact 3.0 steps into 2.999..
+    let due = (clock.accumulated / clock.timestep + 1e-9).floor();
+    let steps = due.min(clock.max_substeps as f64);
+
+    // falling behind is better than taking ever more steps per call to catch up
+    let skipped = (due - steps) * clock.timestep;
+    clock.dropped += skipped;
+    clock.accumulated -= steps * clock.timestep + skipped;
+    clock.elapsed += steps * clock.timestep;
+
+    steps as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_time_turns_into_whole_steps() {
+        let mut c = clock(1.0, 10, 2.0);
+        assert_eq!(substeps(&mut c, 1.5), 3);
+        assert_eq!(c.elapsed, 3.0);
+        assert_eq!(c.accumulated, 0.0);
+    }
+
+    #[test]
+    fn leftover_time_carries_over() {
+        let mut c = clock(1.0, 10, 1.0);
+        assert_eq!(substeps(&mut c, 2.5), 2);
+        assert_eq!(c.accumulated, 0.5);
+        assert_eq!(substeps(&mut c, 0.25), 0);
+        assert_eq!(substeps(&mut c, 0.25), 1);
+        assert_eq!(c.elapsed, 3.0);
+        assert!(c.accumulated.abs() < 1e-12);
+    }
+
+    #[test]
+    fn steps_past_the_cap_are_dropped() {
+        let mut c = clock(1.0, 4, 1.0);
+        assert_eq!(substeps(&mut c, 10.5), 4);
+        assert_eq!(c.dropped, 6.0);
+        assert_eq!(c.accumulated, 0.5);
+
+        // the next call does not try to catch up
+        assert_eq!(substeps(&mut c, 1.0), 1);
+        assert_eq!(c.elapsed, 5.0);
+    }
+
+    #[test]
+    fn a_planck_clock_needs_a_positive_render_time() {
+        for render_time in [0.0, -1.0, f64::NAN] {
+            assert!(planck_clock(render_time, 1.0, 4).is_none());
+        }
+
+        let mut c = planck_clock(1.0 / 60.0, 2.0, 4).unwrap();
+        assert_eq!(substeps(&mut c, 1.0 / 60.0), 2);
+        assert_eq!(c.timestep, TS_F64);
+    }
+}
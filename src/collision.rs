@@ -0,0 +1,857 @@
+use crate::anomaly::{
+    anomaly_components, anomaly_phase, component_center, component_property, set_anomaly_phase,
+    Anomaly,
+};
+use crate::f64_3::{
+    cross_f64_3, dd_f64_3, dot_f64_3, mltply_f64_3, nrmlz_f64_3, sbtr_f64_3, vector_length,
+};
+use crate::integrator::PhaseSpace;
+use crate::mesh_cache::{generated, mesh, size_class};
+use crate::octree::{octree, within};
+use crate::property::MS;
+use crate::rigid::{apply_impulse, apply_matrix, RigidBody};
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+// things that touch push each other apart, components as the spheres around their stones and
+// rigid bodies as the convex hulls of theirs; every touch is given back as a collision
+
+// how many times the simplex or polytope around the origin is grown before giving up on it
+static GJK_ITERATIONS: usize = 64;
+static EPA_ITERATIONS: usize = 64;
+
+// how much of their approach two things keep bouncing apart, and how hard they rub along
+// each other for how hard they press
+#[derive(Debug, Clone, Copy)]
+pub struct Material {
+    pub restitution: f64,
+    pub friction: f64,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            restitution: 0.5,
+            friction: 0.3,
+        }
+    }
+}
+
+// where two shapes overlap, the normal pointing from the first into the second and the depth
+// being how far they have to move apart along it
+#[derive(Debug, Clone, Copy)]
+pub struct Contact {
+    pub normal: [f64; 3],
+    pub depth: f64,
+    pub point: [f64; 3],
+}
+
+// a contact between the a-th and the b-th collider, with the impulse it took along the normal,
+// zero for things already moving apart
+#[derive(Debug, Clone, Copy)]
+pub struct Collision {
+    pub a: usize,
+    pub b: usize,
+    pub contact: Contact,
+    pub impulse: f64,
+}
+
+pub fn sphere_contact(a: [f64; 3], ra: f64, b: [f64; 3], rb: f64) -> Option<Contact> {
+    let diff = sbtr_f64_3(b, a);
+    let distance = vector_length(diff);
+    let depth = ra + rb - distance;
+    if depth <= 0.0 {
+        return None;
+    }
+    // right on top of each other, any way apart does
+    let normal = if distance > 0.0 {
+        mltply_f64_3(diff, 1.0 / distance)
+    } else {
+        [0.0, -1.0, 0.0]
+    };
+    Some(Contact {
+        normal,
+        depth,
+        point: dd_f64_3(a, mltply_f64_3(normal, ra - depth / 2.0)),
+    })
+}
+
+// the collision radius of every size class asked for so far
+static RADII: LazyLock<Mutex<HashMap<Option<i32>, f64>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// a sphere holding any variant of the stones of a size, around the point they are placed at;
+// the variants of a size are about as large, so the first one stands in for them all
+pub fn collision_radius(size: f64) -> f64 {
+    let class = size_class(size);
+    if let Some(radius) = RADII.lock().unwrap().get(&class) {
+        return *radius;
+    }
+
+    let (center, radius) = mesh(&generated(size, 0)).bounds;
+    let radius = radius as f64 + vector_length(center.map(|x| x as f64));
+    RADII.lock().unwrap().insert(class, radius);
+    radius
+}
+
+// a point of the difference of two shapes, and the point of the first one it came from
+#[derive(Debug, Clone, Copy)]
+struct Support {
+    p: [f64; 3],
+    a: [f64; 3],
+}
+
+// a convex shape, known by its point furthest along any direction
+#[derive(Debug, Clone, Copy)]
+enum Convex<'a> {
+    // the hull of the points
+    Hull(&'a [[f64; 3]]),
+    // a center and a radius
+    Ball([f64; 3], f64),
+}
+
+impl Convex<'_> {
+    fn furthest(&self, direction: [f64; 3]) -> [f64; 3] {
+        match self {
+            Convex::Hull(points) => points
+                .iter()
+                .copied()
+                .max_by(|x, y| dot_f64_3(*x, direction).total_cmp(&dot_f64_3(*y, direction)))
+                .unwrap_or([0.0; 3]),
+            Convex::Ball(center, radius) => {
+                dd_f64_3(*center, mltply_f64_3(nrmlz_f64_3(direction), *radius))
+            }
+        }
+    }
+
+    fn middle(&self) -> [f64; 3] {
+        match self {
+            Convex::Hull(points) => mean(points),
+            Convex::Ball(center, _) => *center,
+        }
+    }
+}
+
+fn support(a: Convex, b: Convex, direction: [f64; 3]) -> Support {
+    let pa = a.furthest(direction);
+    let pb = b.furthest(mltply_f64_3(direction, -1.0));
+    Support {
+        p: sbtr_f64_3(pa, pb),
+        a: pa,
+    }
+}
+
+fn mean(points: &[[f64; 3]]) -> [f64; 3] {
+    let sum = points.iter().fold([0.0; 3], |s, p| dd_f64_3(s, *p));
+    mltply_f64_3(sum, 1.0 / points.len().max(1) as f64)
+}
+
+// gilbert-johnson-keerthi on two convex shapes: a tetrahedron of the difference of the
+// shapes around the origin if they overlap, the newest point last
+fn gjk(a: Convex, b: Convex) -> Option<Vec<Support>> {
+    let mut direction = sbtr_f64_3(a.middle(), b.middle());
+    if direction == [0.0; 3] {
+        direction = [1.0, 0.0, 0.0];
+    }
+    let mut simplex = vec![support(a, b, direction)];
+    direction = mltply_f64_3(simplex[0].p, -1.0);
+
+    for _ in 0..GJK_ITERATIONS {
+        // the origin on the simplex, the shapes only touch
+        if direction == [0.0; 3] {
+            return None;
+        }
+        let s = support(a, b, direction);
+        if dot_f64_3(s.p, direction) <= 0.0 {
+            return None;
+        }
+        simplex.push(s);
+        if enclose(&mut simplex, &mut direction) {
+            return Some(simplex);
+        }
+    }
+    None
+}
+
+// cuts the simplex down to the part closest to the origin and points the direction from it
+// towards the origin, true once a tetrahedron holds the origin
+fn enclose(simplex: &mut Vec<Support>, direction: &mut [f64; 3]) -> bool {
+    match simplex.len() {
+        2 => {
+            line(simplex, direction);
+            false
+        }
+        3 => {
+            triangle(simplex, direction);
+            false
+        }
+        _ => tetrahedron(simplex, direction),
+    }
+}
+
+fn line(simplex: &mut Vec<Support>, direction: &mut [f64; 3]) {
+    let (b, a) = (simplex[0], simplex[1]);
+    let ab = sbtr_f64_3(b.p, a.p);
+    let ao = mltply_f64_3(a.p, -1.0);
+    if dot_f64_3(ab, ao) > 0.0 {
+        *direction = cross_f64_3(cross_f64_3(ab, ao), ab);
+    } else {
+        *simplex = vec![a];
+        *direction = ao;
+    }
+}
+
+fn triangle(simplex: &mut Vec<Support>, direction: &mut [f64; 3]) {
+    let (c, b, a) = (simplex[0], simplex[1], simplex[2]);
+    let ab = sbtr_f64_3(b.p, a.p);
+    let ac = sbtr_f64_3(c.p, a.p);
+    let ao = mltply_f64_3(a.p, -1.0);
+    let abc = cross_f64_3(ab, ac);
+
+    if dot_f64_3(cross_f64_3(abc, ac), ao) > 0.0 {
+        if dot_f64_3(ac, ao) > 0.0 {
+            *simplex = vec![c, a];
+            *direction = cross_f64_3(cross_f64_3(ac, ao), ac);
+        } else {
+            *simplex = vec![b, a];
+            line(simplex, direction);
+        }
+    } else if dot_f64_3(cross_f64_3(ab, abc), ao) > 0.0 {
+        *simplex = vec![b, a];
+        line(simplex, direction);
+    } else if dot_f64_3(abc, ao) > 0.0 {
+        *direction = abc;
+    } else {
+        *simplex = vec![b, c, a];
+        *direction = mltply_f64_3(abc, -1.0);
+    }
+}
+
+fn tetrahedron(simplex: &mut Vec<Support>, direction: &mut [f64; 3]) -> bool {
+    let (d, c, b, a) = (simplex[0], simplex[1], simplex[2], simplex[3]);
+    let ao = mltply_f64_3(a.p, -1.0);
+
+    // the three faces through the newest point, each with the point across from it
+    for (face, opposite) in [([c, b, a], d), ([d, c, a], b), ([b, d, a], c)] {
+        let mut normal = cross_f64_3(sbtr_f64_3(face[1].p, a.p), sbtr_f64_3(face[0].p, a.p));
+        if dot_f64_3(normal, sbtr_f64_3(opposite.p, a.p)) > 0.0 {
+            normal = mltply_f64_3(normal, -1.0);
+        }
+        if dot_f64_3(normal, ao) > 0.0 {
+            *simplex = face.to_vec();
+            triangle(simplex, direction);
+            return false;
+        }
+    }
+    true
+}
+
+// the unit normal of a face and how far its plane is from the origin, None for a sliver
+fn plane(points: &[Support], face: &[usize; 3]) -> Option<([f64; 3], f64)> {
+    let [a, b, c] = face.map(|i| points[i].p);
+    let normal = cross_f64_3(sbtr_f64_3(b, a), sbtr_f64_3(c, a));
+    let length = vector_length(normal);
+    if length <= f64::EPSILON * dot_f64_3(a, a).max(1.0) {
+        return None;
+    }
+    let normal = mltply_f64_3(normal, 1.0 / length);
+    Some((normal, dot_f64_3(normal, a)))
+}
+
+// the expanding polytope: the face of the difference of the shapes closest to the origin,
+// grown outwards from the tetrahedron gjk leaves until no point lies further out past it;
+// around a ball that never quite happens and the iterations run out on a close enough face
+fn epa(a: Convex, b: Convex, simplex: Vec<Support>) -> Option<Contact> {
+    let mut points = simplex;
+    let middle = mean(&points.iter().map(|s| s.p).collect::<Vec<_>>());
+    let mut faces: Vec<[usize; 3]> = vec![[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]];
+    // counterclockwise seen from outside
+    for f in faces.iter_mut() {
+        let [p0, p1, p2] = f.map(|i| points[i].p);
+        let normal = cross_f64_3(sbtr_f64_3(p1, p0), sbtr_f64_3(p2, p0));
+        if dot_f64_3(normal, sbtr_f64_3(p0, middle)) < 0.0 {
+            f.swap(1, 2);
+        }
+    }
+
+    let reach = points
+        .iter()
+        .map(|s| vector_length(s.p))
+        .fold(0.0, f64::max);
+    let tolerance = 1e-9 * reach.max(f64::MIN_POSITIVE);
+
+    let mut closest = None;
+    for _ in 0..EPA_ITERATIONS {
+        closest = faces
+            .iter()
+            .filter_map(|f| plane(&points, f).map(|(n, d)| (*f, n, d)))
+            .min_by(|x, y| x.2.total_cmp(&y.2));
+        let (_, normal, distance) = closest?;
+
+        let s = support(a, b, normal);
+        if dot_f64_3(s.p, normal) - distance <= tolerance {
+            break;
+        }
+
+        // the faces the new point sees go, and their rim is closed up to it
+        let mut rim: Vec<(usize, usize)> = vec![];
+        faces.retain(|f| {
+            let normal = cross_f64_3(
+                sbtr_f64_3(points[f[1]].p, points[f[0]].p),
+                sbtr_f64_3(points[f[2]].p, points[f[0]].p),
+            );
+            if dot_f64_3(normal, sbtr_f64_3(s.p, points[f[0]].p)) <= 0.0 {
+                return true;
+            }
+            for (i, j) in [(f[0], f[1]), (f[1], f[2]), (f[2], f[0])] {
+                match rim.iter().position(|e| *e == (j, i)) {
+                    Some(k) => {
+                        rim.swap_remove(k);
+                    }
+                    None => rim.push((i, j)),
+                }
+            }
+            false
+        });
+        points.push(s);
+        let k = points.len() - 1;
+        faces.extend(rim.into_iter().map(|(i, j)| [i, j, k]));
+    }
+
+    let (face, normal, depth) = closest?;
+    Some(Contact {
+        normal,
+        depth,
+        point: contact_point(&points, &face, normal, depth),
+    })
+}
+
+// the origin projected on the closest face, carried over to the first shape by the same
+// weights and then halfway back into the second
+fn contact_point(points: &[Support], face: &[usize; 3], normal: [f64; 3], depth: f64) -> [f64; 3] {
+    let [s0, s1, s2] = face.map(|i| points[i]);
+    let q = mltply_f64_3(normal, depth);
+    let v0 = sbtr_f64_3(s1.p, s0.p);
+    let v1 = sbtr_f64_3(s2.p, s0.p);
+    let v2 = sbtr_f64_3(q, s0.p);
+    let (d00, d01, d11) = (dot_f64_3(v0, v0), dot_f64_3(v0, v1), dot_f64_3(v1, v1));
+    let (d20, d21) = (dot_f64_3(v2, v0), dot_f64_3(v2, v1));
+    let denominator = d00 * d11 - d01 * d01;
+    let (v, w) = if denominator.abs() > 0.0 {
+        (
+            (d11 * d20 - d01 * d21) / denominator,
+            (d00 * d21 - d01 * d20) / denominator,
+        )
+    } else {
+        (1.0 / 3.0, 1.0 / 3.0)
+    };
+    let on_a = dd_f64_3(
+        mltply_f64_3(s0.a, 1.0 - v - w),
+        dd_f64_3(mltply_f64_3(s1.a, v), mltply_f64_3(s2.a, w)),
+    );
+    sbtr_f64_3(on_a, mltply_f64_3(normal, depth / 2.0))
+}
+
+// how two convex shapes, each given by the points it is the hull of, overlap if they do
+pub fn convex_contact(a: &[[f64; 3]], b: &[[f64; 3]]) -> Option<Contact> {
+    if a.is_empty() || b.is_empty() {
+        return None;
+    }
+    overlap(Convex::Hull(a), Convex::Hull(b))
+}
+
+// how a ball overlaps a convex shape given by the points it is the hull of, the normal
+// pointing from the ball into the shape
+pub fn ball_contact(center: [f64; 3], radius: f64, hull: &[[f64; 3]]) -> Option<Contact> {
+    if hull.is_empty() || radius <= 0.0 {
+        return None;
+    }
+    overlap(Convex::Ball(center, radius), Convex::Hull(hull))
+}
+
+fn overlap(a: Convex, b: Convex) -> Option<Contact> {
+    let simplex = gjk(a, b)?;
+    epa(a, b, simplex).filter(|c| c.depth > 0.0)
+}
+
+fn inverse_mass(mass: f64) -> f64 {
+    if mass > 0.0 {
+        1.0 / mass
+    } else {
+        0.0
+    }
+}
+
+// the impulse along the normal that turns the approach into restitution times as fast a
+// parting, and the one against the sliding that stops it or rubs as hard as friction lets it;
+// effective gives how much an impulse along a direction changes the velocity along it
+fn contact_impulses<F: Fn([f64; 3]) -> f64>(
+    relative: [f64; 3],
+    normal: [f64; 3],
+    material: &Material,
+    effective: F,
+) -> Option<(f64, [f64; 3])> {
+    let approach = dot_f64_3(relative, normal);
+    if approach >= 0.0 {
+        return None;
+    }
+    let jn = -(1.0 + material.restitution) * approach / effective(normal);
+
+    let sliding = sbtr_f64_3(relative, mltply_f64_3(normal, approach));
+    let speed = vector_length(sliding);
+    let tangent = nrmlz_f64_3(sliding);
+    let jt = if speed > 0.0 {
+        (speed / effective(tangent)).min(material.friction * jn)
+    } else {
+        0.0
+    };
+
+    Some((
+        jn,
+        sbtr_f64_3(mltply_f64_3(normal, jn), mltply_f64_3(tangent, jt)),
+    ))
+}
+
+// moves the pair apart along the normal until they just touch, each by as much as it is light,
+// which leaves their center of mass where it was
+fn separate(inverse: [f64; 2], normal: [f64; 3], depth: f64) -> [[f64; 3]; 2] {
+    let share = depth / (inverse[0] + inverse[1]);
+    [
+        mltply_f64_3(normal, -share * inverse[0]),
+        mltply_f64_3(normal, share * inverse[1]),
+    ]
+}
+
+// bounces the components of the tree off each other as spheres around their stones, the
+// collisions numbered in the order anomaly_phase lists the components; the massless and
+// those without a place pass through everything
+pub fn collide_components(anom: &mut Anomaly, material: &Material) -> Vec<Collision> {
+    let mut phase = PhaseSpace {
+        position: vec![],
+        inertia: vec![],
+    };
+    anomaly_phase(anom, &mut phase);
+    let solid = solid_components(anom);
+
+    let largest = solid.iter().flatten().map(|s| s.1).fold(0.0, f64::max);
+    let tree = octree(&phase.position);
+
+    let mut collisions = vec![];
+    for i in 0..solid.len() {
+        let (mi, ri) = match solid[i] {
+            Some(s) => s,
+            None => continue,
+        };
+        let mut near = within(&tree, phase.position[i], ri + largest);
+        near.sort_unstable();
+        for j in near.into_iter().filter(|j| *j > i) {
+            let (mj, rj) = match solid[j] {
+                Some(s) => s,
+                None => continue,
+            };
+            let contact = match sphere_contact(phase.position[i], ri, phase.position[j], rj) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            let inverse = [inverse_mass(mi), inverse_mass(mj)];
+            let relative = sbtr_f64_3(phase.inertia[j], phase.inertia[i]);
+            let mut impulse = 0.0;
+            if let Some((jn, j_vector)) =
+                contact_impulses(relative, contact.normal, material, |_| {
+                    inverse[0] + inverse[1]
+                })
+            {
+                impulse = jn;
+                phase.inertia[i] = sbtr_f64_3(phase.inertia[i], mltply_f64_3(j_vector, inverse[0]));
+                phase.inertia[j] = dd_f64_3(phase.inertia[j], mltply_f64_3(j_vector, inverse[1]));
+            }
+
+            let [move_i, move_j] = separate(inverse, contact.normal, contact.depth);
+            phase.position[i] = dd_f64_3(phase.position[i], move_i);
+            phase.position[j] = dd_f64_3(phase.position[j], move_j);
+
+            collisions.push(Collision {
+                a: i,
+                b: j,
+                contact,
+                impulse,
+            });
+        }
+    }
+
+    set_anomaly_phase(anom, &phase, &mut 0);
+    collisions
+}
+
+// the mass and collision radius of every component in the order anomaly_phase lists them,
+// None for those that pass through everything
+fn solid_components(anom: &Anomaly) -> Vec<Option<(f64, f64)>> {
+    let mut components = vec![];
+    anomaly_components(anom, &mut components);
+    components
+        .iter()
+        .map(|c| {
+            let m = component_property(c, MS).filter(|m| *m > 0.0)?;
+            component_center(c)?;
+            Some((m, collision_radius(m)))
+        })
+        .collect()
+}
+
+// the sphere around a body's hull, centered where the body is
+fn body_bounds(body: &RigidBody) -> ([f64; 3], f64) {
+    let reach = body
+        .hull
+        .iter()
+        .map(|p| vector_length(sbtr_f64_3(*p, body.center)))
+        .fold(0.0, f64::max);
+    (body.position, reach)
+}
+
+// the change of relative velocity along a direction an impulse along it makes at a point of
+// a body, for how the body turns about its centroid
+fn turning(body: &RigidBody, point: [f64; 3], direction: [f64; 3]) -> f64 {
+    let arm = sbtr_f64_3(point, body.position);
+    let turn = body.world_inverse_inertia();
+    let spin = cross_f64_3(apply_matrix(&turn, cross_f64_3(arm, direction)), arm);
+    dot_f64_3(direction, spin)
+}
+
+// bounces the components of the tree, as the spheres around their stones, off the convex
+// hulls of rigid bodies; a of every collision is the component numbered as in
+// collide_components and b the body
+pub fn collide_components_bodies(
+    anom: &mut Anomaly,
+    bodies: &mut [RigidBody],
+    material: &Material,
+) -> Vec<Collision> {
+    let mut phase = PhaseSpace {
+        position: vec![],
+        inertia: vec![],
+    };
+    anomaly_phase(anom, &mut phase);
+    let solid = solid_components(anom);
+
+    let largest = solid.iter().flatten().map(|s| s.1).fold(0.0, f64::max);
+    let tree = octree(&phase.position);
+
+    let mut collisions = vec![];
+    for (j, body) in bodies.iter_mut().enumerate() {
+        let (center, reach) = body_bounds(body);
+        let mut near = within(&tree, center, reach + largest);
+        near.sort_unstable();
+
+        let hull: Vec<[f64; 3]> = body.hull.iter().map(|p| body.world_point(*p)).collect();
+        for i in near {
+            let (mi, ri) = match solid[i] {
+                Some(s) => s,
+                None => continue,
+            };
+            let contact = match ball_contact(phase.position[i], ri, &hull) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            let inverse = [inverse_mass(mi), inverse_mass(body.mass)];
+            let relative = sbtr_f64_3(body.point_velocity(contact.point), phase.inertia[i]);
+            let effective = |d: [f64; 3]| inverse[0] + inverse[1] + turning(body, contact.point, d);
+            let mut impulse = 0.0;
+            if let Some((jn, j_vector)) =
+                contact_impulses(relative, contact.normal, material, effective)
+            {
+                impulse = jn;
+                phase.inertia[i] = sbtr_f64_3(phase.inertia[i], mltply_f64_3(j_vector, inverse[0]));
+                apply_impulse(body, j_vector, contact.point);
+            }
+
+            let [move_i, move_body] = separate(inverse, contact.normal, contact.depth);
+            phase.position[i] = dd_f64_3(phase.position[i], move_i);
+            body.position = dd_f64_3(body.position, move_body);
+
+            collisions.push(Collision {
+                a: i,
+                b: j,
+                contact,
+                impulse,
+            });
+        }
+    }
+
+    set_anomaly_phase(anom, &phase, &mut 0);
+    collisions
+}
+
+// bounces rigid bodies off each other where their convex hulls overlap, turning them as much
+// as the contact is off their centroids
+pub fn collide_bodies(bodies: &mut [RigidBody], material: &Material) -> Vec<Collision> {
+    let mut collisions = vec![];
+    for i in 0..bodies.len() {
+        for j in i + 1..bodies.len() {
+            let (left, right) = bodies.split_at_mut(j);
+            let (a, b) = (&mut left[i], &mut right[0]);
+
+            // the spheres around them first, most pairs are far apart
+            let ((ca, ra), (cb, rb)) = (body_bounds(a), body_bounds(b));
+            if sphere_contact(ca, ra, cb, rb).is_none() {
+                continue;
+            }
+
+            let hull_a: Vec<[f64; 3]> = a.hull.iter().map(|p| a.world_point(*p)).collect();
+            let hull_b: Vec<[f64; 3]> = b.hull.iter().map(|p| b.world_point(*p)).collect();
+            let contact = match convex_contact(&hull_a, &hull_b) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            let inverse = [inverse_mass(a.mass), inverse_mass(b.mass)];
+            if inverse == [0.0, 0.0] {
+                continue;
+            }
+            let effective = |d: [f64; 3]| {
+                inverse[0]
+                    + inverse[1]
+                    + turning(a, contact.point, d)
+                    + turning(b, contact.point, d)
+            };
+
+            let relative = sbtr_f64_3(
+                b.point_velocity(contact.point),
+                a.point_velocity(contact.point),
+            );
+            let mut impulse = 0.0;
+            if let Some((jn, j_vector)) =
+                contact_impulses(relative, contact.normal, material, effective)
+            {
+                impulse = jn;
+                apply_impulse(a, mltply_f64_3(j_vector, -1.0), contact.point);
+                apply_impulse(b, j_vector, contact.point);
+            }
+
+            let [move_a, move_b] = separate(inverse, contact.normal, contact.depth);
+            a.position = dd_f64_3(a.position, move_a);
+            b.position = dd_f64_3(b.position, move_b);
+
+            collisions.push(Collision {
+                a: i,
+                b: j,
+                contact,
+                impulse,
+            });
+        }
+    }
+    collisions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anomaly::{Component, Composition, Property};
+    use crate::hull::hull_stone;
+    use crate::mesh_cache::MeshKey;
+    use crate::positions::Position;
+    use crate::property::{IN0, IN1, IN2};
+    use crate::rigid::stone_body;
+
+    fn ball(position: [f64; 3], velocity: [f64; 3], mass: f64) -> Component {
+        let property = |name, value| Property { name, value };
+        Component {
+            component: vec![],
+            composition: vec![Composition {
+                space: vec![position.map(|x| x as f32)],
+                distribution: vec![],
+            }],
+            property: vec![
+                property(MS, mass),
+                property(IN0, velocity[0]),
+                property(IN1, velocity[1]),
+                property(IN2, velocity[2]),
+            ],
+            asset: None,
+        }
+    }
+
+    fn balls(components: Vec<Component>) -> Anomaly {
+        Anomaly {
+            anomaly: vec![],
+            component: components,
+            force: vec![],
+            theta: 0.5,
+            integration: None,
+        }
+    }
+
+    fn phase(anom: &Anomaly) -> PhaseSpace {
+        let mut phase = PhaseSpace {
+            position: vec![],
+            inertia: vec![],
+        };
+        anomaly_phase(anom, &mut phase);
+        phase
+    }
+
+    // a box of the given half sizes around its centroid at position, of density one
+    fn block(position: [f64; 3], half: [f64; 3], velocity: [f64; 3]) -> RigidBody {
+        let corners: Vec<Position> = (0..8)
+            .map(|i| Position {
+                position: [0, 1, 2].map(|d| {
+                    let sign = if (i >> d) & 1 == 1 { 1.0 } else { -1.0 };
+                    (sign * half[d]) as f32
+                }),
+            })
+            .collect();
+        let key = MeshKey::Asset("block".to_string());
+        let mut body = stone_body(key, &hull_stone(&corners), position, 1.0);
+        body.momentum = mltply_f64_3(velocity, body.mass);
+        body
+    }
+
+    fn angular_momentum(bodies: &[RigidBody]) -> [f64; 3] {
+        bodies.iter().fold([0.0; 3], |sum, b| {
+            let orbital = cross_f64_3(b.position, b.momentum);
+            dd_f64_3(sum, dd_f64_3(orbital, b.angular_momentum))
+        })
+    }
+
+    fn close(a: [f64; 3], b: [f64; 3], tolerance: f64) -> bool {
+        vector_length(sbtr_f64_3(a, b)) <= tolerance * vector_length(b).max(1.0)
+    }
+
+    #[test]
+    fn head_on_spheres_bounce_back() {
+        let (ma, mb) = (1.0, 2.0);
+        let gap = 0.9 * (collision_radius(ma) + collision_radius(mb));
+        let mut anom = balls(vec![
+            ball([0.0; 3], [1.0, 0.0, 0.0], ma),
+            ball([gap, 0.0, 0.0], [-1.0, 0.0, 0.0], mb),
+        ]);
+        let material = Material {
+            restitution: 0.5,
+            friction: 0.3,
+        };
+
+        let before = phase(&anom);
+        let collisions = collide_components(&mut anom, &material);
+        let after = phase(&anom);
+
+        assert_eq!(collisions.len(), 1);
+        assert_eq!((collisions[0].a, collisions[0].b), (0, 1));
+        assert!(close(collisions[0].contact.normal, [1.0, 0.0, 0.0], 1e-9));
+        assert!(collisions[0].impulse > 0.0);
+
+        let momentum = |p: &PhaseSpace| {
+            dd_f64_3(
+                mltply_f64_3(p.inertia[0], ma),
+                mltply_f64_3(p.inertia[1], mb),
+            )
+        };
+        assert!(close(momentum(&after), momentum(&before), 1e-12));
+
+        let approach = sbtr_f64_3(before.inertia[1], before.inertia[0])[0];
+        let parting = sbtr_f64_3(after.inertia[1], after.inertia[0])[0];
+        assert!((parting + material.restitution * approach).abs() < 1e-12);
+
+        // pushed apart until they just touch
+        let apart = vector_length(sbtr_f64_3(after.position[1], after.position[0]));
+        let touching = collision_radius(ma) + collision_radius(mb);
+        assert!((apart - touching).abs() < 1e-4 * touching);
+    }
+
+    #[test]
+    fn spheres_apart_or_massless_pass_by() {
+        let r = collision_radius(1.0);
+        let mut anom = balls(vec![
+            ball([0.0; 3], [1.0, 0.0, 0.0], 1.0),
+            ball([2.1 * r, 0.0, 0.0], [-1.0, 0.0, 0.0], 1.0),
+            ball([0.1 * r, 0.0, 0.0], [0.0; 3], 0.0),
+        ]);
+        assert!(collide_components(&mut anom, &Material::default()).is_empty());
+    }
+
+    #[test]
+    fn two_boxes_meet_by_their_hulls() {
+        let material = Material {
+            restitution: 0.8,
+            friction: 0.0,
+        };
+        let mut bodies = vec![
+            block([0.0, 0.0, 0.0], [0.5, 0.5, 0.5], [1.0, 0.0, 0.0]),
+            block([0.9, 0.2, -0.1], [0.5, 0.3, 0.4], [-1.0, 0.0, 0.0]),
+        ];
+        let momentum = dd_f64_3(bodies[0].momentum, bodies[1].momentum);
+        let turning = angular_momentum(&bodies);
+
+        let collisions = collide_bodies(&mut bodies, &material);
+        assert_eq!(collisions.len(), 1);
+        let contact = collisions[0].contact;
+        assert!(close(contact.normal, [1.0, 0.0, 0.0], 1e-6));
+        assert!((contact.depth - 0.1).abs() < 1e-6);
+
+        let approach = dot_f64_3(
+            sbtr_f64_3(
+                block([0.9, 0.2, -0.1], [0.5, 0.3, 0.4], [-1.0, 0.0, 0.0]).velocity(),
+                [1.0, 0.0, 0.0],
+            ),
+            contact.normal,
+        );
+        let parting = dot_f64_3(
+            sbtr_f64_3(
+                bodies[1].point_velocity(contact.point),
+                bodies[0].point_velocity(contact.point),
+            ),
+            contact.normal,
+        );
+        assert!((parting + material.restitution * approach).abs() < 1e-9);
+
+        assert!(close(
+            dd_f64_3(bodies[0].momentum, bodies[1].momentum),
+            momentum,
+            1e-12
+        ));
+        // the impulses act at one point, so the angular momentum about the origin stays
+        // as well; the separation moves the bodies, so it is compared before that
+        let mut unmoved = vec![
+            block([0.0, 0.0, 0.0], [0.5, 0.5, 0.5], [1.0, 0.0, 0.0]),
+            block([0.9, 0.2, -0.1], [0.5, 0.3, 0.4], [-1.0, 0.0, 0.0]),
+        ];
+        for (u, b) in unmoved.iter_mut().zip(&bodies) {
+            u.momentum = b.momentum;
+            u.angular_momentum = b.angular_momentum;
+        }
+        assert!(close(angular_momentum(&unmoved), turning, 1e-9));
+
+        // left just touching and parting, nothing more to push
+        let again = collide_bodies(&mut bodies, &material);
+        assert!(again
+            .iter()
+            .all(|c| c.impulse == 0.0 && c.contact.depth < 1e-9));
+    }
+
+    #[test]
+    fn a_sphere_bounces_off_a_box() {
+        let material = Material {
+            restitution: 0.5,
+            friction: 0.0,
+        };
+        let r = collision_radius(1.0);
+        let mut anom = balls(vec![ball([-0.5 - 0.9 * r, 0.0, 0.0], [2.0, 0.0, 0.0], 1.0)]);
+        let mut bodies = vec![block([0.0; 3], [0.5, 0.5, 0.5], [0.0; 3])];
+
+        let collisions = collide_components_bodies(&mut anom, &mut bodies, &material);
+        assert_eq!(collisions.len(), 1);
+        let contact = collisions[0].contact;
+        assert!(close(contact.normal, [1.0, 0.0, 0.0], 1e-3));
+        assert!((contact.depth - 0.1 * r).abs() < 1e-3 * r);
+
+        let after = phase(&anom);
+        let momentum = dd_f64_3(after.inertia[0], bodies[0].momentum);
+        assert!(close(momentum, [2.0, 0.0, 0.0], 1e-12));
+
+        let parting = dot_f64_3(
+            sbtr_f64_3(bodies[0].point_velocity(contact.point), after.inertia[0]),
+            contact.normal,
+        );
+        assert!((parting - material.restitution * 2.0).abs() < 1e-9);
+    }
+}
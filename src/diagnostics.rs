@@ -0,0 +1,256 @@
+use crate::anomaly::{
+    anomaly_components, component_center, component_inertia, component_property, kinetic_energy,
+    potential_energy, Anomaly,
+};
+use crate::f64_3::{dd_f64_3, mltply_f64_3, sbtr_f64_3, vector_length};
+use crate::property::{EC, MS};
+
+use std::fmt;
+
+// what a tree of anomalies adds up to at one moment, compared from step to step to catch force
+// code that stops conserving what it should
+
+#[derive(Debug, Clone, Copy)]
+pub struct Diagnostics {
+    pub momentum: [f64; 3],
+    // the sizes of all the momenta added up, what a change of the total is measured against
+    pub motion: f64,
+    pub kinetic: f64,
+    pub potential: f64,
+    pub charge: f64,
+    // the sizes of all the charges added up, what a change of the total is measured against
+    pub charges: f64,
+    pub mass: f64,
+    pub center_of_mass: [f64; 3],
+    // the box around every point the components are made of
+    pub extent: ([f64; 3], [f64; 3]),
+}
+
+impl Diagnostics {
+    pub fn energy(&self) -> f64 {
+        self.kinetic + self.potential
+    }
+}
+
+pub fn diagnose(anom: &Anomaly) -> Diagnostics {
+    let mut components = vec![];
+    anomaly_components(anom, &mut components);
+
+    let mut momentum = [0.0; 3];
+    let mut motion = 0.0;
+    let mut charge = 0.0;
+    let mut charges = 0.0;
+    let mut mass = 0.0;
+    let mut weighted = [0.0; 3];
+    let mut low = [f64::MAX; 3];
+    let mut high = [f64::MIN; 3];
+    for c in components {
+        let q = component_property(c, EC).unwrap_or(0.0);
+        charge += q;
+        charges += q.abs();
+
+        let m = component_property(c, MS).unwrap_or(0.0);
+        let p = mltply_f64_3(component_inertia(c), m);
+        momentum = dd_f64_3(momentum, p);
+        motion += vector_length(p);
+        if let Some(center) = component_center(c) {
+            mass += m;
+            weighted = dd_f64_3(weighted, mltply_f64_3(center, m));
+        }
+
+        for s in c.composition.iter().flat_map(|c| c.space.iter()) {
+            for d in 0..3 {
+                low[d] = low[d].min(s[d] as f64);
+                high[d] = high[d].max(s[d] as f64);
+            }
+        }
+    }
+
+    Diagnostics {
+        momentum,
+        motion,
+        kinetic: kinetic_energy(anom),
+        potential: potential_energy(anom),
+        charge,
+        charges,
+        mass,
+        center_of_mass: if mass > 0.0 {
+            mltply_f64_3(weighted, 1.0 / mass)
+        } else {
+            [0.0; 3]
+        },
+        extent: if low[0] <= high[0] {
+            (low, high)
+        } else {
+            ([0.0; 3], [0.0; 3])
+        },
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "momentum {:.3e} {:.3e} {:.3e}, kinetic {:.3e}, potential {:.3e}, charge {:.3}, \
+             center of mass {:.2} {:.2} {:.2}, extent {:.2} {:.2} {:.2} to {:.2} {:.2} {:.2}",
+            self.momentum[0],
+            self.momentum[1],
+            self.momentum[2],
+            self.kinetic,
+            self.potential,
+            self.charge,
+            self.center_of_mass[0],
+            self.center_of_mass[1],
+            self.center_of_mass[2],
+            self.extent.0[0],
+            self.extent.0[1],
+            self.extent.0[2],
+            self.extent.1[0],
+            self.extent.1[1],
+            self.extent.1[2],
+        )
+    }
+}
+
+// a conserved quantity that wandered off, by how much relative to what it is measured against
+#[derive(Debug, Clone, PartialEq)]
+pub enum Drift {
+    // against all the motion there was at the start
+    Momentum { change: f64 },
+    // against the total energy at the start
+    Energy { change: f64 },
+    // against all the charge there was at the start, of either sign
+    Charge { change: f64 },
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Drift::Momentum { change } => write!(f, "momentum drifted by {:.3e}", change),
+            Drift::Energy { change } => write!(f, "energy drifted by {:.3e}", change),
+            Drift::Charge { change } => write!(f, "charge drifted by {:.3e}", change),
+        }
+    }
+}
+
+// the quantities that moved further than tolerance from where they started; collisions that
+// lose energy on purpose show up here as well
+pub fn drifts(start: &Diagnostics, now: &Diagnostics, tolerance: f64) -> Vec<Drift> {
+    let mut ret = vec![];
+
+    let momentum = vector_length(sbtr_f64_3(now.momentum, start.momentum))
+        / start.motion.max(f64::MIN_POSITIVE);
+    if momentum > tolerance {
+        ret.push(Drift::Momentum { change: momentum });
+    }
+
+    let energy = (now.energy() - start.energy()) / start.energy().abs().max(f64::MIN_POSITIVE);
+    if energy.abs() > tolerance {
+        ret.push(Drift::Energy { change: energy });
+    }
+
+    let charge = (now.charge - start.charge) / start.charges.max(f64::MIN_POSITIVE);
+    if charge.abs() > tolerance {
+        ret.push(Drift::Charge { change: charge });
+    }
+
+    ret
+}
+
+// prints what the tree adds up to now, and a warning for every drift since the start
+pub fn report(anom: &Anomaly, start: &Diagnostics, tolerance: f64) -> Diagnostics {
+    let now = diagnose(anom);
+    println!("{}", now);
+    for drift in drifts(start, &now, tolerance) {
+        eprintln!("warning: {}", drift);
+    }
+    now
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anomaly::{integrate, Component, Composition, Force, Property};
+    use crate::integrator::Integration;
+    use crate::property::{IN0, IN1, IN2};
+    use crate::scheduler::scheduler;
+
+    fn charged(position: [f32; 3], velocity: [f64; 3], charge: f64) -> Component {
+        let property = |name, value| Property { name, value };
+        Component {
+            component: vec![],
+            composition: vec![Composition {
+                space: vec![position],
+                distribution: vec![],
+            }],
+            property: vec![
+                property(MS, 1.0),
+                property(EC, charge),
+                property(IN0, velocity[0]),
+                property(IN1, velocity[1]),
+                property(IN2, velocity[2]),
+            ],
+            asset: None,
+        }
+    }
+
+    // two charges circling each other and a third circling them further out, held together by
+    // a pull of one between all masses and nothing coming in from outside
+    fn closed() -> Anomaly {
+        let gravity = Force {
+            force: vec![],
+            range: vec![],
+            domain: vec![Component {
+                component: vec![],
+                composition: vec![],
+                property: vec![Property {
+                    name: MS,
+                    value: 1.0,
+                }],
+                asset: None,
+            }],
+        };
+        Anomaly {
+            anomaly: vec![],
+            component: vec![
+                charged([-1.0, 0.0, 0.0], [0.0, 0.0, -0.5], 1.0),
+                charged([1.0, 0.0, 0.0], [0.0, 0.0, 0.5], -1.0),
+                charged([0.0, 8.0, 0.0], [0.5, 0.0, 0.0], 2.0),
+            ],
+            force: vec![gravity],
+            theta: 0.0,
+            integration: None,
+        }
+    }
+
+    #[test]
+    fn a_closed_scene_keeps_what_it_should() {
+        let mut anom = closed();
+        let start = diagnose(&anom);
+        assert_eq!(start.charges, 4.0);
+
+        let scheduler = scheduler(1);
+        for _ in 0..400 {
+            integrate(&mut anom, &scheduler, Integration::VelocityVerlet, 0.05);
+        }
+        let now = diagnose(&anom);
+
+        assert!(now.center_of_mass != start.center_of_mass);
+        assert_eq!(drifts(&start, &now, 1e-4), vec![]);
+    }
+
+    #[test]
+    fn charge_drifts_against_all_the_charge() {
+        let start = diagnose(&closed());
+        let mut now = start;
+
+        now.charge = start.charge + 1e-4 * start.charges;
+        assert_eq!(drifts(&start, &now, 1e-3), vec![]);
+
+        now.charge = start.charge - 1e-2 * start.charges;
+        match drifts(&start, &now, 1e-3)[..] {
+            [Drift::Charge { change }] => assert!((change + 1e-2).abs() < 1e-12),
+            ref other => panic!("{:?}", other),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use std::sync::Mutex;
+
+// functions spreading the points of a composition over the places its component shows up at,
+// carried with their names so scenes can refer to them
+
+pub type Distribute = fn(Vec<[f32; 3]>) -> Vec<[f32; 3]>;
+
+#[derive(Clone)]
+pub struct Distribution {
+    pub name: String,
+    pub distribute: Distribute,
+}
+
+static REGISTRY: Mutex<Vec<Distribution>> = Mutex::new(Vec::new());
+
+static BUILT_IN: [(&str, Distribute); 1] = [("particular", particular)];
+
+// makes a distribution known under a name, a later registration under the same name replaces it
+pub fn register_distribution(name: &str, distribute: Distribute) -> Distribution {
+    let d = Distribution {
+        name: name.to_string(),
+        distribute,
+    };
+
+    let mut registry = REGISTRY.lock().unwrap();
+    registry.retain(|r| r.name != name);
+    registry.push(d.clone());
+
+    d
+}
+
+pub fn distribution_by_name(name: &str) -> Option<Distribution> {
+    if let Some(b) = BUILT_IN.iter().find(|b| b.0 == name) {
+        return Some(Distribution {
+            name: b.0.to_string(),
+            distribute: b.1,
+        });
+    }
+
+    let registry = REGISTRY.lock().unwrap();
+    registry.iter().find(|r| r.name == name).cloned()
+}
+
+pub fn distribute(distribution: &Distribution, coordinates: Vec<[f32; 3]>) -> Vec<[f32; 3]> {
+    (distribution.distribute)(coordinates)
+}
+
+// every point where it is
+pub fn particular(coordinates: Vec<[f32; 3]>) -> Vec<[f32; 3]> {
+    coordinates
+}
+
+impl Serialize for Distribution {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.name)
+    }
+}
+
+// only names registered before loading can be read back
+impl<'de> Deserialize<'de> for Distribution {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        distribution_by_name(&name)
+            .ok_or_else(|| D::Error::custom(format!("unknown distribution {}", name)))
+    }
+}
@@ -0,0 +1,409 @@
+use crate::magma_ocean::Stone;
+use crate::noise::{fractal, Displacement, Noise};
+use crate::normals::{smooth_normals, Weighting};
+use crate::positions::Position;
+
+use rand::Rng;
+
+// landscapes worn down on a grid of heights, by water running over them and by slopes too
+// steep to hold, then turned into a stone to be looked at
+
+pub struct Heightfield {
+    pub width: usize, // points along x
+    pub depth: usize, // points along z
+    pub spacing: f32, // between neighbouring points
+    pub heights: Vec<f32>,
+}
+
+impl Heightfield {
+    fn height(&self, x: usize, z: usize) -> f32 {
+        self.heights[z * self.width + x]
+    }
+
+    // bilinear height and its gradient anywhere between the points, in grid units
+    fn sample(&self, x: f32, z: f32) -> (f32, [f32; 2]) {
+        let (i, j) = (x as usize, z as usize);
+        let (u, v) = (x - i as f32, z - j as f32);
+
+        let h00 = self.height(i, j);
+        let h10 = self.height(i + 1, j);
+        let h01 = self.height(i, j + 1);
+        let h11 = self.height(i + 1, j + 1);
+
+        let height =
+            h00 * (1.0 - u) * (1.0 - v) + h10 * u * (1.0 - v) + h01 * (1.0 - u) * v + h11 * u * v;
+        let gradient = [
+            (h10 - h00) * (1.0 - v) + (h11 - h01) * v,
+            (h01 - h00) * (1.0 - u) + (h11 - h10) * u,
+        ];
+        (height, gradient)
+    }
+
+    // spreads a change of height over the four points around a place
+    fn add(&mut self, x: f32, z: f32, amount: f32) {
+        let (i, j) = (x as usize, z as usize);
+        let (u, v) = (x - i as f32, z - j as f32);
+        let w = self.width;
+
+        self.heights[j * w + i] += amount * (1.0 - u) * (1.0 - v);
+        self.heights[j * w + i + 1] += amount * u * (1.0 - v);
+        self.heights[(j + 1) * w + i] += amount * (1.0 - u) * v;
+        self.heights[(j + 1) * w + i + 1] += amount * u * v;
+    }
+
+    // spreads a change of height over the points within a radius (grid units) of a place,
+    // more of it the closer they are
+    fn spread(&mut self, x: f32, z: f32, radius: f32, amount: f32) {
+        let reach = radius.ceil() as isize;
+        let mut weights = vec![];
+        for dz in -reach..=reach {
+            for dx in -reach..=reach {
+                let (i, j) = (x as isize + dx, z as isize + dz);
+                if i < 0 || j < 0 || i >= self.width as isize || j >= self.depth as isize {
+                    continue;
+                }
+                let distance = ((i as f32 - x).powi(2) + (j as f32 - z).powi(2)).sqrt();
+                if distance < radius {
+                    weights.push((j as usize * self.width + i as usize, radius - distance));
+                }
+            }
+        }
+
+        let total: f32 = weights.iter().map(|w| w.1).sum();
+        for (k, w) in weights {
+            self.heights[k] += amount * w / total;
+        }
+    }
+}
+
+// a landscape of noise, the displacement giving its hills in units of space
+pub fn terrain(
+    width: usize,
+    depth: usize,
+    spacing: f32,
+    noise: &Noise,
+    displacement: &Displacement,
+) -> Heightfield {
+    let mut heights = vec![];
+    for z in 0..depth {
+        for x in 0..width {
+            let p = [x as f32 * spacing, 0.5, z as f32 * spacing];
+            heights.push(fractal(noise, displacement, p));
+        }
+    }
+
+    Heightfield {
+        width,
+        depth,
+        spacing,
+        heights,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Hydraulic {
+    pub droplets: u32,
+    pub lifetime: u32,     // steps a droplet runs at most
+    pub inertia: f32,      // how much a droplet keeps its direction instead of following the slope
+    pub capacity: f32,     // sediment carried per unit of drop, speed and water
+    pub min_capacity: f32, // so droplets on flat ground still carry a little
+    pub erosion: f32,      // share of the free capacity taken from the ground per step
+    pub radius: f32,       // of the ground taken, in grid points
+    pub deposition: f32,   // share of the excess sediment dropped per step
+    pub evaporation: f32,  // share of the water lost per step
+    pub gravity: f32,
+    pub friction: f32, // share of the speed lost per step, or deepening valleys run away
+}
+
+impl Default for Hydraulic {
+    fn default() -> Self {
+        Hydraulic {
+            droplets: 50000,
+            lifetime: 30,
+            inertia: 0.05,
+            capacity: 1.0,
+            min_capacity: 0.01,
+            erosion: 0.3,
+            radius: 3.0,
+            deposition: 0.3,
+            evaporation: 0.01,
+            gravity: 1.0,
+            friction: 0.3,
+        }
+    }
+}
+
+// droplets fall at random places and run downhill, picking up ground where they speed up
+// and dropping it where they slow down, fill a pit or dry up
+pub fn erode_hydraulic<R: Rng + ?Sized>(
+    field: &mut Heightfield,
+    hydraulic: &Hydraulic,
+    rng: &mut R,
+) {
+    let (w, d) = (
+        field.width.saturating_sub(1) as f32,
+        field.depth.saturating_sub(1) as f32,
+    );
+    if w < 1.0 || d < 1.0 {
+        return;
+    }
+
+    for _ in 0..hydraulic.droplets {
+        let mut x = rng.gen_range(0.0..w);
+        let mut z = rng.gen_range(0.0..d);
+        let mut direction = [0.0_f32; 2];
+        let mut speed = 1.0_f32;
+        let mut water = 1.0_f32;
+        let mut sediment = 0.0_f32;
+
+        for _ in 0..hydraulic.lifetime {
+            let (height, gradient) = field.sample(x, z);
+
+            direction = [
+                direction[0] * hydraulic.inertia - gradient[0] * (1.0 - hydraulic.inertia),
+                direction[1] * hydraulic.inertia - gradient[1] * (1.0 - hydraulic.inertia),
+            ];
+            let length = (direction[0].powi(2) + direction[1].powi(2)).sqrt();
+            if length == 0.0 {
+                break; // nowhere to run
+            }
+            direction = [direction[0] / length, direction[1] / length];
+
+            let (nx, nz) = (x + direction[0], z + direction[1]);
+            if nx < 0.0 || nz < 0.0 || nx >= w || nz >= d {
+                sediment = 0.0; // ran off the edge with what it carries
+                break;
+            }
+
+            let drop = field.sample(nx, nz).0 - height;
+            let capacity = (-drop * speed * water * hydraulic.capacity).max(hydraulic.min_capacity);
+
+            if drop > 0.0 || sediment > capacity {
+                // uphill the pit behind is filled as far as the sediment goes
+                let deposit = if drop > 0.0 {
+                    drop.min(sediment)
+                } else {
+                    (sediment - capacity) * hydraulic.deposition
+                };
+                sediment -= deposit;
+                field.add(x, z, deposit);
+            } else {
+                // never digging deeper than the drop, so no pits are left behind
+                let taken = ((capacity - sediment) * hydraulic.erosion).min(-drop);
+                sediment += taken;
+                field.spread(x, z, hydraulic.radius.max(1.0), -taken);
+            }
+
+            speed = (speed * speed - drop * hydraulic.gravity).max(0.0).sqrt()
+                * (1.0 - hydraulic.friction);
+            water *= 1.0 - hydraulic.evaporation;
+            x = nx;
+            z = nz;
+        }
+
+        // what a droplet still carries when it stops stays where it is
+        field.add(x, z, sediment);
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Thermal {
+    pub iterations: u32,
+    pub talus: f32, // the steepest slope that holds, height per unit of distance
+    pub rate: f32,  // share of the excess that slides per iteration, up to half
+}
+
+impl Default for Thermal {
+    fn default() -> Self {
+        Thermal {
+            iterations: 50,
+            talus: 0.8,
+            rate: 0.5,
+        }
+    }
+}
+
+// material slides from every point to its lower neighbours wherever the slope between them
+// is steeper than the talus, all points at once per iteration
+pub fn erode_thermal(field: &mut Heightfield, thermal: &Thermal) {
+    let (w, d) = (field.width, field.depth);
+    let neighbours: [(isize, isize, f32); 8] = [
+        (-1, 0, 1.0),
+        (1, 0, 1.0),
+        (0, -1, 1.0),
+        (0, 1, 1.0),
+        (-1, -1, std::f32::consts::SQRT_2),
+        (1, -1, std::f32::consts::SQRT_2),
+        (-1, 1, std::f32::consts::SQRT_2),
+        (1, 1, std::f32::consts::SQRT_2),
+    ];
+
+    for _ in 0..thermal.iterations {
+        let mut change = vec![0.0; field.heights.len()];
+
+        for z in 0..d {
+            for x in 0..w {
+                let h = field.height(x, z);
+
+                // what exceeds the talus towards every lower neighbour
+                let mut excess = vec![];
+                for (dx, dz, distance) in neighbours {
+                    let (nx, nz) = (x as isize + dx, z as isize + dz);
+                    if nx < 0 || nz < 0 || nx >= w as isize || nz >= d as isize {
+                        continue;
+                    }
+                    let n = nz as usize * w + nx as usize;
+                    let e = h - field.heights[n] - thermal.talus * distance * field.spacing;
+                    if e > 0.0 {
+                        excess.push((n, e));
+                    }
+                }
+
+                // the largest excess sets how much goes, shared out by each neighbour's excess
+                let total: f32 = excess.iter().map(|e| e.1).sum();
+                let largest = excess.iter().fold(0.0_f32, |m, e| m.max(e.1));
+                let moved = thermal.rate.min(0.5) * largest;
+                for (n, e) in excess {
+                    change[n] += moved * e / total;
+                }
+                change[z * w + x] -= moved;
+            }
+        }
+
+        for (h, c) in field.heights.iter_mut().zip(change) {
+            *h += c;
+        }
+    }
+}
+
+// the landscape as one sheet of triangles around the origin, heights rising towards -y,
+// which the renderer shows as up
+pub fn heightfield_stone(field: &Heightfield) -> Stone {
+    let (w, d) = (field.width, field.depth);
+    if w == 0 || d == 0 {
+        return Stone {
+            positions: vec![],
+            normals: vec![],
+            indices: vec![],
+        };
+    }
+
+    let half = [
+        (w - 1) as f32 * field.spacing / 2.0,
+        (d - 1) as f32 * field.spacing / 2.0,
+    ];
+
+    let mut positions = vec![];
+    for z in 0..d {
+        for x in 0..w {
+            positions.push(Position {
+                position: [
+                    x as f32 * field.spacing - half[0],
+                    -field.height(x, z),
+                    z as f32 * field.spacing - half[1],
+                ],
+            });
+        }
+    }
+
+    // counterclockwise seen from above
+    let mut indices = vec![];
+    for z in 0..d.saturating_sub(1) {
+        for x in 0..w.saturating_sub(1) {
+            let a = (z * w + x) as u32;
+            let b = a + 1;
+            let c = a + w as u32;
+            indices.extend([a, b, c, b, c + 1, c]);
+        }
+    }
+
+    let mut stone = Stone {
+        positions,
+        normals: vec![],
+        indices,
+    };
+    smooth_normals(&mut stone, Weighting::Area, None);
+    stone
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::{noise, Kind};
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn hills(seed: u64) -> Heightfield {
+        let displacement = Displacement {
+            kind: Kind::Fbm,
+            octaves: 4,
+            frequency: 0.1,
+            amplitude: 6.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        };
+        terrain(
+            32,
+            24,
+            0.5,
+            &noise(&mut StdRng::seed_from_u64(seed)),
+            &displacement,
+        )
+    }
+
+    fn eroded(seed: u64) -> Vec<f32> {
+        let mut field = hills(seed);
+        let hydraulic = Hydraulic {
+            droplets: 2000,
+            ..Default::default()
+        };
+        erode_hydraulic(&mut field, &hydraulic, &mut StdRng::seed_from_u64(seed));
+        erode_thermal(&mut field, &Thermal::default());
+        field.heights
+    }
+
+    #[test]
+    fn the_same_seed_wears_down_the_same_landscape() {
+        assert_eq!(eroded(7), eroded(7));
+        assert_ne!(eroded(7), eroded(8));
+    }
+
+    #[test]
+    fn sliding_keeps_all_the_material() {
+        let mut field = hills(3);
+        let before: f64 = field.heights.iter().map(|h| *h as f64).sum();
+        erode_thermal(
+            &mut field,
+            &Thermal {
+                iterations: 200,
+                talus: 0.2,
+                rate: 0.5,
+            },
+        );
+        assert_ne!(field.heights, hills(3).heights);
+        let after: f64 = field.heights.iter().map(|h| *h as f64).sum();
+
+        let scale: f64 = field.heights.iter().map(|h| h.abs() as f64).sum();
+        assert!(
+            (after - before).abs() < 1e-5 * scale,
+            "{} became {}",
+            before,
+            after
+        );
+    }
+
+    #[test]
+    fn an_empty_field_gives_an_empty_stone() {
+        for (width, depth) in [(0, 0), (0, 5), (5, 0)] {
+            let field = Heightfield {
+                width,
+                depth,
+                spacing: 1.0,
+                heights: vec![],
+            };
+            let stone = heightfield_stone(&field);
+            assert!(stone.positions.is_empty() && stone.indices.is_empty());
+        }
+    }
+}
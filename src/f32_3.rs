@@ -1,4 +1,3 @@
-use rand::rngs::ThreadRng;
 use rand::Rng;
 use std::f32::consts::PI;
 
@@ -40,7 +39,7 @@ pub fn vector_length(x: [f32; 3]) -> f32 {
     return (x[0].powi(2) + x[1].powi(2) + x[2].powi(2)).sqrt();
 }
 
-pub fn gen_f32_3(base: f32, range: f32, rng: &mut ThreadRng) -> [f32; 3] {
+pub fn gen_f32_3<R: Rng + ?Sized>(base: f32, range: f32, rng: &mut R) -> [f32; 3] {
     return [
         rng.gen_range(base - range..base + range),
         rng.gen_range(base - range..base + range),
@@ -48,7 +47,7 @@ pub fn gen_f32_3(base: f32, range: f32, rng: &mut ThreadRng) -> [f32; 3] {
     ];
 }
 
-pub fn gen_rthgnl_f32_3(a: [f32; 3], rng: &mut ThreadRng) -> [f32; 3] {
+pub fn gen_rthgnl_f32_3<R: Rng + ?Sized>(a: [f32; 3], rng: &mut R) -> [f32; 3] {
     let mut x = 0.0;
     let mut y = 0.0;
     let mut z = 0.0;
@@ -91,6 +90,15 @@ pub fn find_points_normal(x: [f32; 3], y: [f32; 3]) -> [f32; 3] {
     return nrmlz_f32_3(sbtr_f32_3(y, x));
 }
 
+// not normalized, its length is twice the area of the triangle the two vectors span
+pub fn cross_f32_3(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    return [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ];
+}
+
 pub fn dot_product(a: [f32; 3], b: [f32; 3]) -> f32 {
     return a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
 }
@@ -132,13 +140,3 @@ pub fn angular_difference(a: f32, b: f32) -> f32 {
         return 2.0 * PI - diffbst;
     }
 }
-
-pub fn gen_f32_3_unit_on_point_normal_plane(
-    planes_normal: [f32; 3],
-    planes_point: [f32; 3],
-    unit: f32,
-    rng: &mut ThreadRng,
-) -> [f32; 3] {
-    let random_vector_on_plane = gen_rthgnl_f32_3(planes_normal, rng);
-    return dd_f32_3(mltply_f32_3(random_vector_on_plane, unit), planes_point);
-}
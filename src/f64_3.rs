@@ -1,7 +1,6 @@
-use rand::rngs::ThreadRng;
 use rand::Rng;
 
-pub fn gen_f64_3(base: f64, range: f64, rng: &mut ThreadRng) -> [f64; 3] {
+pub fn gen_f64_3<R: Rng + ?Sized>(base: f64, range: f64, rng: &mut R) -> [f64; 3] {
     return [
         rng.gen_range(base - range..base + range),
         rng.gen_range(base - range..base + range),
@@ -9,6 +8,14 @@ pub fn gen_f64_3(base: f64, range: f64, rng: &mut ThreadRng) -> [f64; 3] {
     ];
 }
 
+pub fn sbtr_f64_3(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    return [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+}
+
+pub fn dd_f64_3(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    return [a[0] + b[0], a[1] + b[1], a[2] + b[2]];
+}
+
 pub fn nrmlz_f64_3(a: [f64; 3]) -> [f64; 3] {
     let m = vector_length(a);
     if m > 0.0 {
@@ -25,3 +32,15 @@ pub fn mltply_f64_3(a: [f64; 3], b: f64) -> [f64; 3] {
 pub fn vector_length(x: [f64; 3]) -> f64 {
     return (x[0].powi(2) + x[1].powi(2) + x[2].powi(2)).sqrt();
 }
+
+pub fn dot_f64_3(a: [f64; 3], b: [f64; 3]) -> f64 {
+    return a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+}
+
+pub fn cross_f64_3(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    return [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ];
+}
@@ -0,0 +1,446 @@
+use crate::f64_3::{
+    cross_f64_3, dd_f64_3, dot_f64_3, mltply_f64_3, nrmlz_f64_3, sbtr_f64_3, vector_length,
+};
+use crate::magma_ocean::Stone;
+use crate::normals::{smooth_normals, Weighting};
+use crate::positions::{Normal, Position};
+
+use std::collections::{HashMap, HashSet};
+
+// convex hull of a point set, grown one point at a time from a tetrahedron;
+// faces are index triples into the points, counterclockwise seen from outside,
+// points inside or on the hull within a small tolerance are left out;
+// the points go in the order given, which join_rings relies on, quickhull is faster
+
+pub fn convex_hull(points: &[[f64; 3]]) -> Vec<[usize; 3]> {
+    if points.len() < 4 {
+        return vec![];
+    }
+
+    let tolerance = tolerance(points);
+    let start = match tetrahedron(points, tolerance) {
+        Some(t) => t,
+        None => return vec![], // flat, nothing encloses a volume
+    };
+
+    let mut faces = first_faces(points, start);
+
+    for (i, p) in points.iter().enumerate() {
+        if start.contains(&i) {
+            continue;
+        }
+
+        let visible: Vec<bool> = faces
+            .iter()
+            .map(|f| face_distance(points, *f, *p) > tolerance)
+            .collect();
+        if !visible.iter().any(|v| *v) {
+            continue;
+        }
+
+        // edges between a visible and a hidden face, kept in the direction of the visible one
+        let mut horizon = vec![];
+        for (f, face) in faces.iter().enumerate() {
+            if !visible[f] {
+                continue;
+            }
+            for e in 0..3 {
+                let edge = [face[e], face[(e + 1) % 3]];
+                let shared = faces.iter().enumerate().any(|(g, other)| {
+                    visible[g]
+                        && (0..3).any(|o| other[o] == edge[1] && other[(o + 1) % 3] == edge[0])
+                });
+                if !shared {
+                    horizon.push(edge);
+                }
+            }
+        }
+
+        let mut kept: Vec<[usize; 3]> = faces
+            .iter()
+            .enumerate()
+            .filter(|(f, _)| !visible[*f])
+            .map(|(_, face)| *face)
+            .collect();
+        for edge in horizon {
+            kept.push([edge[0], edge[1], i]);
+        }
+        faces = kept;
+    }
+
+    faces
+}
+
+// the same hull by quickhull: every face holds the points in front of it and grows by the one
+// furthest out, so points inside the hull are dropped as soon as it covers them
+pub fn quickhull(points: &[[f64; 3]]) -> Vec<[usize; 3]> {
+    if points.len() < 4 {
+        return vec![];
+    }
+
+    let tolerance = tolerance(points);
+    let start = match tetrahedron(points, tolerance) {
+        Some(t) => t,
+        None => return vec![],
+    };
+
+    let mut faces = first_faces(points, start);
+    let mut alive = vec![true; faces.len()];
+    let mut outside: Vec<Vec<usize>> = vec![vec![]; faces.len()];
+    // every directed edge with the face it goes around
+    let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+    for (f, face) in faces.iter().enumerate() {
+        for e in 0..3 {
+            edges.insert((face[e], face[(e + 1) % 3]), f);
+        }
+    }
+    for (i, p) in points.iter().enumerate() {
+        if start.contains(&i) {
+            continue;
+        }
+        if let Some(f) = (0..faces.len()).find(|f| face_distance(points, faces[*f], *p) > tolerance)
+        {
+            outside[f].push(i);
+        }
+    }
+
+    let mut f = 0;
+    while f < faces.len() {
+        if !alive[f] || outside[f].is_empty() {
+            f += 1;
+            continue;
+        }
+        let distance = |i: &usize| face_distance(points, faces[f], points[*i]);
+        let p = *outside[f]
+            .iter()
+            .max_by(|a, b| distance(a).total_cmp(&distance(b)))
+            .unwrap();
+
+        // the faces p sees, spreading out from this one over their neighbours, and the edges
+        // where they meet the ones it does not see
+        let mut visible = vec![f];
+        let mut seen: HashSet<usize> = HashSet::from([f]);
+        let mut horizon = vec![];
+        let mut k = 0;
+        while k < visible.len() {
+            let face = faces[visible[k]];
+            k += 1;
+            for e in 0..3 {
+                let (a, b) = (face[e], face[(e + 1) % 3]);
+                let neighbour = edges[&(b, a)];
+                if seen.contains(&neighbour) {
+                    continue;
+                }
+                if face_distance(points, faces[neighbour], points[p]) > tolerance {
+                    seen.insert(neighbour);
+                    visible.push(neighbour);
+                } else {
+                    horizon.push((a, b));
+                }
+            }
+        }
+
+        let mut orphans = vec![];
+        for g in visible {
+            alive[g] = false;
+            orphans.append(&mut outside[g]);
+            for e in 0..3 {
+                edges.remove(&(faces[g][e], faces[g][(e + 1) % 3]));
+            }
+        }
+
+        let first = faces.len();
+        for (a, b) in horizon {
+            let g = faces.len();
+            faces.push([a, b, p]);
+            alive.push(true);
+            outside.push(vec![]);
+            for edge in [(a, b), (b, p), (p, a)] {
+                edges.insert(edge, g);
+            }
+        }
+        for q in orphans {
+            if q == p {
+                continue;
+            }
+            if let Some(g) = (first..faces.len())
+                .find(|g| face_distance(points, faces[*g], points[q]) > tolerance)
+            {
+                outside[g].push(q);
+            }
+        }
+    }
+
+    faces
+        .into_iter()
+        .zip(alive)
+        .filter(|(_, a)| *a)
+        .map(|(face, _)| face)
+        .collect()
+}
+
+// the convex hull of the positions as a closed stone of its own, only the corners of the hull
+// kept and numbered anew; empty when the positions span no volume
+pub fn hull_stone(positions: &[Position]) -> Stone {
+    let points: Vec<[f64; 3]> = positions
+        .iter()
+        .map(|p| p.position.map(|x| x as f64))
+        .collect();
+
+    let mut stone = Stone {
+        positions: vec![],
+        normals: vec![],
+        indices: vec![],
+    };
+    let mut renumbered: HashMap<usize, u32> = HashMap::new();
+    for face in quickhull(&points) {
+        for i in face {
+            let v = *renumbered.entry(i).or_insert_with(|| {
+                stone.positions.push(positions[i]);
+                stone.positions.len() as u32 - 1
+            });
+            stone.indices.push(v);
+        }
+    }
+    stone.normals = vec![Normal { normal: [0.0; 3] }; stone.positions.len()];
+    smooth_normals(&mut stone, Weighting::Angle, None);
+    stone
+}
+
+// a simple shape to stand in for the stone, for collisions and the like
+pub fn stone_hull(stone: &Stone) -> Stone {
+    hull_stone(&stone.positions)
+}
+
+// whether the points of a ring that make its outline come in the ring in the order they go
+// around it, one way or the other; the outline is read off the hull of the ring and a point
+// lifted off it along the normal, whose faces all run along the outline
+pub fn ring_ordered(ring: &[Position], normal: [f32; 3]) -> bool {
+    let mut points: Vec<[f64; 3]> = ring.iter().map(|p| p.position.map(|x| x as f64)).collect();
+    if points.len() < 3 {
+        return true;
+    }
+    let middle = mltply_f64_3(
+        points.iter().fold([0.0; 3], |s, p| dd_f64_3(s, *p)),
+        1.0 / points.len() as f64,
+    );
+    let spread = points
+        .iter()
+        .map(|p| vector_length(sbtr_f64_3(*p, middle)))
+        .fold(0.0, f64::max);
+    let apex = points.len();
+    points.push(dd_f64_3(
+        middle,
+        mltply_f64_3(nrmlz_f64_3(normal.map(|x| x as f64)), spread),
+    ));
+
+    // every face at the apex holds one edge of the outline, all going around the same way
+    let mut next: HashMap<usize, usize> = HashMap::new();
+    for face in quickhull(&points) {
+        if let Some(a) = face.iter().position(|i| *i == apex) {
+            next.insert(face[(a + 1) % 3], face[(a + 2) % 3]);
+        }
+    }
+    let first = match next.keys().min() {
+        Some(f) => *f,
+        None => return true, // all in a line, nothing to go around
+    };
+
+    let mut outline = vec![first];
+    while let Some(n) = next.get(outline.last().unwrap()) {
+        if *n == first || outline.len() > next.len() {
+            break;
+        }
+        outline.push(*n);
+    }
+    if outline.len() != next.len() {
+        return false;
+    }
+
+    // in order the ring numbers go up around the outline and wrap around once, or go down
+    let steps: Vec<bool> = (0..outline.len())
+        .map(|k| outline[(k + 1) % outline.len()] > outline[k])
+        .collect();
+    let up = steps.iter().filter(|s| **s).count();
+    up <= 1 || up + 1 >= steps.len()
+}
+
+// how far off a face a point may lie and still count as on it, small against the spread of the points
+fn tolerance(points: &[[f64; 3]]) -> f64 {
+    let mut min = points[0];
+    let mut max = points[0];
+    for p in points {
+        for d in 0..3 {
+            min[d] = min[d].min(p[d]);
+            max[d] = max[d].max(p[d]);
+        }
+    }
+    1e-9 * vector_length(sbtr_f64_3(max, min)).max(f64::MIN_POSITIVE)
+}
+
+// the tetrahedron's faces, turned outwards
+fn first_faces(points: &[[f64; 3]], start: [usize; 4]) -> Vec<[usize; 3]> {
+    let mut faces: Vec<[usize; 3]> = vec![
+        [start[0], start[1], start[2]],
+        [start[0], start[3], start[1]],
+        [start[1], start[3], start[2]],
+        [start[2], start[3], start[0]],
+    ];
+    // turn the faces outwards if the fourth point lies in front of the first face
+    if face_distance(points, faces[0], points[start[3]]) > 0.0 {
+        for f in faces.iter_mut() {
+            f.swap(1, 2);
+        }
+    }
+
+    faces
+}
+
+// outward normal of a face, not normalized
+pub fn face_normal(points: &[[f64; 3]], face: [usize; 3]) -> [f64; 3] {
+    cross_f64_3(
+        sbtr_f64_3(points[face[1]], points[face[0]]),
+        sbtr_f64_3(points[face[2]], points[face[0]]),
+    )
+}
+
+// signed distance of a point in front of a face
+fn face_distance(points: &[[f64; 3]], face: [usize; 3], p: [f64; 3]) -> f64 {
+    let normal = nrmlz_f64_3(face_normal(points, face));
+    dot_f64_3(normal, sbtr_f64_3(p, points[face[0]]))
+}
+
+// four points spanning a volume, picked as far apart as they come
+fn tetrahedron(points: &[[f64; 3]], tolerance: f64) -> Option<[usize; 4]> {
+    let farthest = |score: &dyn Fn([f64; 3]) -> f64| {
+        let mut best = (0, f64::MIN);
+        for (i, p) in points.iter().enumerate() {
+            let s = score(*p);
+            if s > best.1 {
+                best = (i, s);
+            }
+        }
+        best
+    };
+
+    let a = farthest(&|p| p[0]).0;
+    let (b, ab) = farthest(&|p| vector_length(sbtr_f64_3(p, points[a])));
+    if ab <= tolerance {
+        return None;
+    }
+
+    let line = nrmlz_f64_3(sbtr_f64_3(points[b], points[a]));
+    let (c, abc) = farthest(&|p| vector_length(cross_f64_3(line, sbtr_f64_3(p, points[a]))));
+    if abc <= tolerance {
+        return None;
+    }
+
+    let plane = nrmlz_f64_3(face_normal(points, [a, b, c]));
+    let (d, abcd) = farthest(&|p| dot_f64_3(plane, sbtr_f64_3(p, points[a])).abs());
+    if abcd <= tolerance {
+        return None;
+    }
+
+    Some([a, b, c, d])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magma_ocean::pebble;
+    use crate::validation::validate;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+    use std::f64::consts::PI;
+
+    fn scattered(count: usize, rng: &mut StdRng) -> Vec<[f64; 3]> {
+        (0..count)
+            .map(|_| [0, 1, 2].map(|_| rng.gen_range(-10.0..10.0)))
+            .collect()
+    }
+
+    // every point on the inner side of every face's plane, give or take the tolerance
+    fn holds_all(points: &[[f64; 3]], faces: &[[usize; 3]]) -> bool {
+        let tolerance = tolerance(points);
+        !faces.is_empty()
+            && faces.iter().all(|f| {
+                points
+                    .iter()
+                    .all(|p| face_distance(points, *f, *p) <= tolerance)
+            })
+    }
+
+    #[test]
+    fn hulls_hold_all_their_points() {
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let points = scattered(rng.gen_range(4..300), &mut rng);
+            assert!(holds_all(&points, &quickhull(&points)), "seed {}", seed);
+            assert!(holds_all(&points, &convex_hull(&points)), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn hull_stones_are_closed_and_outward() {
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let points: Vec<Position> = scattered(rng.gen_range(4..300), &mut rng)
+                .iter()
+                .map(|p| Position {
+                    position: p.map(|x| x as f32),
+                })
+                .collect();
+            let report = validate(&hull_stone(&points));
+            assert!(report.is_valid(), "seed {}: {}", seed, report);
+
+            let report = validate(&pebble(rng.gen_range(4..100), 5.0, &mut rng));
+            assert!(report.is_valid(), "pebble from seed {}: {}", seed, report);
+        }
+    }
+
+    #[test]
+    fn flat_points_and_sizeless_pebbles_make_no_stone() {
+        let flat: Vec<Position> = (0..10)
+            .map(|i| Position {
+                position: [i as f32, (i * i) as f32, 0.0],
+            })
+            .collect();
+        assert!(hull_stone(&flat).indices.is_empty());
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for size in [0.0, -1.0, f32::NAN] {
+            assert!(pebble(20, size, &mut rng).positions.is_empty());
+        }
+    }
+
+    // points around a circle in the xz plane at the angles, in the order given
+    fn ring(angles: &[f64]) -> Vec<Position> {
+        angles
+            .iter()
+            .map(|a| Position {
+                position: [a.cos() as f32, 0.0, a.sin() as f32],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rings_in_order_either_way_round() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut angles: Vec<f64> = (0..16).map(|_| rng.gen_range(0.0..2.0 * PI)).collect();
+        angles.sort_by(f64::total_cmp);
+        let normal = [0.0, 1.0, 0.0];
+
+        assert!(ring_ordered(&ring(&angles), normal));
+        let mut reversed = angles.clone();
+        reversed.reverse();
+        assert!(ring_ordered(&ring(&reversed), normal));
+        // starting anywhere along it
+        reversed.rotate_left(5);
+        assert!(ring_ordered(&ring(&reversed), normal));
+
+        let mut swapped = angles.clone();
+        swapped.swap(3, 9);
+        assert!(!ring_ordered(&ring(&swapped), normal));
+    }
+}
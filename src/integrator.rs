@@ -0,0 +1,152 @@
+use crate::f64_3::{dd_f64_3, mltply_f64_3};
+
+use serde::{Deserialize, Serialize};
+
+// positions and inertia (velocities) of every component taking part in a step,
+// in the order the anomaly tree is walked
+
+#[derive(Debug, Clone)]
+pub struct PhaseSpace {
+    pub position: Vec<[f64; 3]>,
+    pub inertia: Vec<[f64; 3]>,
+}
+
+pub trait Integrator {
+    fn integrate(
+        &self,
+        phase: &mut PhaseSpace,
+        time: f64,
+        acceleration: &mut dyn FnMut(&PhaseSpace) -> Vec<[f64; 3]>,
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Integration {
+    ExplicitEuler,
+    SemiImplicitEuler,
+    VelocityVerlet,
+    RungeKutta4,
+}
+
+pub struct ExplicitEuler;
+pub struct SemiImplicitEuler;
+pub struct VelocityVerlet;
+pub struct RungeKutta4;
+
+impl Integrator for Integration {
+    fn integrate(
+        &self,
+        phase: &mut PhaseSpace,
+        time: f64,
+        acceleration: &mut dyn FnMut(&PhaseSpace) -> Vec<[f64; 3]>,
+    ) {
+        match self {
+            Integration::ExplicitEuler => ExplicitEuler.integrate(phase, time, acceleration),
+            Integration::SemiImplicitEuler => {
+                SemiImplicitEuler.integrate(phase, time, acceleration)
+            }
+            Integration::VelocityVerlet => VelocityVerlet.integrate(phase, time, acceleration),
+            Integration::RungeKutta4 => RungeKutta4.integrate(phase, time, acceleration),
+        }
+    }
+}
+
+impl Integrator for ExplicitEuler {
+    fn integrate(
+        &self,
+        phase: &mut PhaseSpace,
+        time: f64,
+        acceleration: &mut dyn FnMut(&PhaseSpace) -> Vec<[f64; 3]>,
+    ) {
+        let a = acceleration(phase);
+
+        phase.position = advance(&phase.position, &phase.inertia, time);
+        phase.inertia = advance(&phase.inertia, &a, time);
+    }
+}
+
+impl Integrator for SemiImplicitEuler {
+    fn integrate(
+        &self,
+        phase: &mut PhaseSpace,
+        time: f64,
+        acceleration: &mut dyn FnMut(&PhaseSpace) -> Vec<[f64; 3]>,
+    ) {
+        let a = acceleration(phase);
+
+        phase.inertia = advance(&phase.inertia, &a, time);
+        phase.position = advance(&phase.position, &phase.inertia, time);
+    }
+}
+
+impl Integrator for VelocityVerlet {
+    fn integrate(
+        &self,
+        phase: &mut PhaseSpace,
+        time: f64,
+        acceleration: &mut dyn FnMut(&PhaseSpace) -> Vec<[f64; 3]>,
+    ) {
+        let a0 = acceleration(phase);
+
+        // half kick, drift, then the second half kick with the acceleration at the new positions
+        phase.inertia = advance(&phase.inertia, &a0, time / 2.0);
+        phase.position = advance(&phase.position, &phase.inertia, time);
+
+        let a1 = acceleration(phase);
+        phase.inertia = advance(&phase.inertia, &a1, time / 2.0);
+    }
+}
+
+impl Integrator for RungeKutta4 {
+    fn integrate(
+        &self,
+        phase: &mut PhaseSpace,
+        time: f64,
+        acceleration: &mut dyn FnMut(&PhaseSpace) -> Vec<[f64; 3]>,
+    ) {
+        let x0 = phase.position.clone();
+        let v0 = phase.inertia.clone();
+
+        let v1 = v0.clone();
+        let a1 = acceleration(phase);
+
+        let s2 = PhaseSpace {
+            position: advance(&x0, &v1, time / 2.0),
+            inertia: advance(&v0, &a1, time / 2.0),
+        };
+        let v2 = s2.inertia.clone();
+        let a2 = acceleration(&s2);
+
+        let s3 = PhaseSpace {
+            position: advance(&x0, &v2, time / 2.0),
+            inertia: advance(&v0, &a2, time / 2.0),
+        };
+        let v3 = s3.inertia.clone();
+        let a3 = acceleration(&s3);
+
+        let s4 = PhaseSpace {
+            position: advance(&x0, &v3, time),
+            inertia: advance(&v0, &a3, time),
+        };
+        let v4 = s4.inertia.clone();
+        let a4 = acceleration(&s4);
+
+        phase.position = advance(&x0, &weigh(&v1, &v2, &v3, &v4), time / 6.0);
+        phase.inertia = advance(&v0, &weigh(&a1, &a2, &a3, &a4), time / 6.0);
+    }
+}
+
+// a + rate * time, element by element
+pub fn advance(a: &[[f64; 3]], rate: &[[f64; 3]], time: f64) -> Vec<[f64; 3]> {
+    a.iter()
+        .zip(rate.iter())
+        .map(|(x, r)| dd_f64_3(*x, mltply_f64_3(*r, time)))
+        .collect()
+}
+
+// k1 + 2 k2 + 2 k3 + k4
+fn weigh(k1: &[[f64; 3]], k2: &[[f64; 3]], k3: &[[f64; 3]], k4: &[[f64; 3]]) -> Vec<[f64; 3]> {
+    let k12 = advance(k1, k2, 2.0);
+    let k123 = advance(&k12, k3, 2.0);
+    advance(&k123, k4, 1.0)
+}
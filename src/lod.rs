@@ -0,0 +1,505 @@
+use crate::f64_3::{cross_f64_3, dot_f64_3, nrmlz_f64_3, sbtr_f64_3, vector_length};
+use crate::magma_ocean::Stone;
+use crate::normals::{smooth_normals, Weighting};
+use crate::positions::Position;
+use crate::validation::welded;
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+// coarser stones for farther away, by collapsing the edges whose loss changes the surface
+// least, measured by the quadric error of Garland and Heckbert, and which of them to draw
+
+// levels stop getting coarser below this many triangles
+static MIN_TRIANGLES: usize = 16;
+// projected height in pixels from which a stone is drawn in full, every halving of it a level less
+static FULL_DETAIL_PIXELS: f32 = 200.0;
+// how much more than a face the planes along open borders count, so borders stay where they are
+static BORDER_WEIGHT: f64 = 1000.0;
+
+// the sum of squared distances to a set of planes, as a symmetric 4x4 matrix
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    // the plane through point with unit normal n, counting weight times
+    fn plane(n: [f64; 3], point: [f64; 3], weight: f64) -> Quadric {
+        let d = -dot_f64_3(n, point);
+        let [a, b, c] = n;
+        Quadric(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|x| x * weight),
+        )
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut q = self.0;
+        for (x, y) in q.iter_mut().zip(other.0) {
+            *x += y;
+        }
+        Quadric(q)
+    }
+
+    fn error(&self, p: [f64; 3]) -> f64 {
+        let [a, b, c, d, e, f, g, h, i, j] = self.0;
+        let [x, y, z] = p;
+        a * x * x
+            + 2.0 * b * x * y
+            + 2.0 * c * x * z
+            + 2.0 * d * x
+            + e * y * y
+            + 2.0 * f * y * z
+            + 2.0 * g * y
+            + h * z * z
+            + 2.0 * i * z
+            + j
+    }
+
+    // the point of least error, if there is just one
+    fn optimum(&self) -> Option<[f64; 3]> {
+        let [a, b, c, d, e, f, g, h, i, _] = self.0;
+        let m = [[a, b, c], [b, e, f], [c, f, h]];
+        let det = determinant(m);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        // cramer's rule on the gradient set to zero
+        let rhs = [-d, -g, -i];
+        Some([0, 1, 2].map(|k| {
+            let mut replaced = m;
+            for (row, r) in replaced.iter_mut().zip(rhs) {
+                row[k] = r;
+            }
+            determinant(replaced) / det
+        }))
+    }
+}
+
+fn determinant(m: [[f64; 3]; 3]) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+// an edge that may be collapsed, and the stamps of its ends when it was weighed
+struct Collapse {
+    cost: f64,
+    ends: [usize; 2],
+    stamps: [u32; 2],
+    to: [f64; 3],
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost.total_cmp(&other.cost) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// the cheapest on top of the heap
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Simplifier {
+    points: Vec<[f64; 3]>,
+    quadrics: Vec<Quadric>,
+    stamps: Vec<u32>,
+    alive: Vec<bool>,
+    triangles: Vec<[usize; 3]>,
+    live_triangles: Vec<bool>,
+    around: Vec<Vec<usize>>, // the triangles at every point
+}
+
+impl Simplifier {
+    fn neighbours(&self, p: usize) -> Vec<usize> {
+        let mut n: Vec<usize> = self.around[p]
+            .iter()
+            .flat_map(|t| self.triangles[*t])
+            .filter(|q| *q != p)
+            .collect();
+        n.sort();
+        n.dedup();
+        n
+    }
+
+    fn weigh(&self, a: usize, b: usize) -> Collapse {
+        let q = self.quadrics[a].add(&self.quadrics[b]);
+        let middle = [0, 1, 2].map(|d| (self.points[a][d] + self.points[b][d]) / 2.0);
+        let to = q.optimum().unwrap_or_else(|| {
+            [self.points[a], self.points[b], middle]
+                .into_iter()
+                .min_by(|x, y| q.error(*x).total_cmp(&q.error(*y)))
+                .unwrap()
+        });
+        Collapse {
+            cost: q.error(to),
+            ends: [a, b],
+            stamps: [self.stamps[a], self.stamps[b]],
+            to,
+        }
+    }
+
+    // collapsing keeps the surface a surface if the ends share no neighbours but the
+    // corners across the edge, and turns no triangle over
+    fn allowed(&self, a: usize, b: usize, to: [f64; 3]) -> bool {
+        let across = self.around[a]
+            .iter()
+            .filter(|t| self.triangles[**t].contains(&b))
+            .count();
+        let nb = self.neighbours(b);
+        let shared = self
+            .neighbours(a)
+            .iter()
+            .filter(|n| nb.binary_search(n).is_ok())
+            .count();
+        if shared > across {
+            return false;
+        }
+
+        for (p, other) in [(a, b), (b, a)] {
+            for t in &self.around[p] {
+                let tri = self.triangles[*t];
+                if tri.contains(&other) {
+                    continue;
+                }
+                let corners = tri.map(|c| self.points[c]);
+                let moved = tri.map(|c| if c == p { to } else { self.points[c] });
+                let before = cross_f64_3(
+                    sbtr_f64_3(corners[1], corners[0]),
+                    sbtr_f64_3(corners[2], corners[0]),
+                );
+                let after = cross_f64_3(
+                    sbtr_f64_3(moved[1], moved[0]),
+                    sbtr_f64_3(moved[2], moved[0]),
+                );
+                if dot_f64_3(before, after) <= 0.0 {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    // b goes into a, which moves to where the collapse puts it
+    fn collapse(&mut self, a: usize, b: usize, to: [f64; 3]) -> usize {
+        let mut removed = 0;
+        for t in std::mem::take(&mut self.around[b]) {
+            if self.triangles[t].contains(&a) {
+                self.live_triangles[t] = false;
+                removed += 1;
+                for c in self.triangles[t] {
+                    self.around[c].retain(|u| *u != t);
+                }
+            } else {
+                for c in self.triangles[t].iter_mut() {
+                    if *c == b {
+                        *c = a;
+                    }
+                }
+                self.around[a].push(t);
+            }
+        }
+
+        self.points[a] = to;
+        self.quadrics[a] = self.quadrics[a].add(&self.quadrics[b]);
+        self.alive[b] = false;
+        self.stamps[a] += 1;
+        removed
+    }
+}
+
+// the stone with its triangles cut down to about target; vertices at one place are merged,
+// so split normals come out smooth again
+pub fn simplify(stone: &Stone, target: usize) -> Stone {
+    let places = welded(&stone.positions);
+
+    // one point for every place, and the triangles between them
+    let mut point_of: HashMap<u32, usize> = HashMap::new();
+    let mut points = vec![];
+    for place in &places {
+        point_of.entry(*place).or_insert_with(|| {
+            points.push(stone.positions[*place as usize].position.map(|x| x as f64));
+            points.len() - 1
+        });
+    }
+    let triangles: Vec<[usize; 3]> = stone
+        .indices
+        .chunks_exact(3)
+        .map(|t| [0, 1, 2].map(|d| point_of[&places[t[d] as usize]]))
+        .filter(|[a, b, c]| a != b && b != c && c != a)
+        .collect();
+
+    let mut s = Simplifier {
+        quadrics: vec![Quadric::default(); points.len()],
+        stamps: vec![0; points.len()],
+        alive: vec![true; points.len()],
+        live_triangles: vec![true; triangles.len()],
+        around: vec![vec![]; points.len()],
+        points,
+        triangles,
+    };
+
+    // the planes of the faces around every point, by area, and of the borders standing up on them
+    let mut edge_count: HashMap<[usize; 2], (usize, [f64; 3])> = HashMap::new();
+    for (t, tri) in s.triangles.iter().enumerate() {
+        let [a, b, c] = tri.map(|i| s.points[i]);
+        let face = cross_f64_3(sbtr_f64_3(b, a), sbtr_f64_3(c, a));
+        let area = vector_length(face) / 2.0;
+        if area > 0.0 {
+            let plane = Quadric::plane(face.map(|x| x / (2.0 * area)), a, area);
+            for i in tri {
+                s.quadrics[*i] = s.quadrics[*i].add(&plane);
+            }
+        }
+        for i in tri {
+            s.around[*i].push(t);
+        }
+        for e in [[tri[0], tri[1]], [tri[1], tri[2]], [tri[2], tri[0]]] {
+            edge_count
+                .entry([e[0].min(e[1]), e[0].max(e[1])])
+                .or_insert((0, face))
+                .0 += 1;
+        }
+    }
+    for (e, (count, face)) in &edge_count {
+        if *count != 1 {
+            continue;
+        }
+        let along = sbtr_f64_3(s.points[e[1]], s.points[e[0]]);
+        let normal = cross_f64_3(along, *face);
+        if vector_length(normal) > 0.0 {
+            let n = nrmlz_f64_3(normal);
+            let plane = Quadric::plane(n, s.points[e[0]], BORDER_WEIGHT * dot_f64_3(along, along));
+            for i in e {
+                s.quadrics[*i] = s.quadrics[*i].add(&plane);
+            }
+        }
+    }
+
+    let mut heap: BinaryHeap<Collapse> = edge_count.keys().map(|e| s.weigh(e[0], e[1])).collect();
+    let mut left = s.triangles.len();
+    while left > target {
+        let Some(c) = heap.pop() else {
+            break;
+        };
+        let [a, b] = c.ends;
+        if !s.alive[a] || !s.alive[b] || c.stamps != [s.stamps[a], s.stamps[b]] {
+            continue; // weighed before one of its ends changed
+        }
+        if !s.allowed(a, b, c.to) {
+            continue;
+        }
+        left -= s.collapse(a, b, c.to);
+        for n in s.neighbours(a) {
+            heap.push(s.weigh(a, n));
+        }
+    }
+
+    // what is left, with the points numbered again
+    let mut number = vec![u32::MAX; s.points.len()];
+    let mut positions = vec![];
+    let mut indices = vec![];
+    for (t, tri) in s.triangles.iter().enumerate() {
+        if !s.live_triangles[t] {
+            continue;
+        }
+        for i in tri {
+            if number[*i] == u32::MAX {
+                number[*i] = positions.len() as u32;
+                positions.push(Position {
+                    position: s.points[*i].map(|x| x as f32),
+                });
+            }
+            indices.push(number[*i]);
+        }
+    }
+
+    let mut simple = Stone {
+        positions,
+        normals: vec![],
+        indices,
+    };
+    smooth_normals(&mut simple, Weighting::Angle, None);
+    simple
+}
+
+// the stone itself and then ever coarser ones, each with about half the triangles of the one
+// before, as many as count or until they stop getting coarser
+pub fn levels(stone: Stone, count: usize) -> Vec<Stone> {
+    let mut levels = vec![stone];
+    while levels.len() < count {
+        let last = &levels[levels.len() - 1];
+        let triangles = last.indices.len() / 3;
+        if triangles / 2 < MIN_TRIANGLES {
+            break;
+        }
+        let coarser = simplify(last, triangles / 2);
+        if coarser.indices.len() >= last.indices.len() {
+            break;
+        }
+        levels.push(coarser);
+    }
+    levels
+}
+
+// the center of the stone's box and the radius of a sphere around it holding every position
+pub fn bounding_sphere(stone: &Stone) -> ([f32; 3], f32) {
+    if stone.positions.is_empty() {
+        return ([0.0; 3], 0.0);
+    }
+    let mut low = [f32::MAX; 3];
+    let mut high = [f32::MIN; 3];
+    for p in &stone.positions {
+        for d in 0..3 {
+            low[d] = low[d].min(p.position[d]);
+            high[d] = high[d].max(p.position[d]);
+        }
+    }
+    let center = [0, 1, 2].map(|d| (low[d] + high[d]) / 2.0);
+    let radius = stone
+        .positions
+        .iter()
+        .map(|p| {
+            let v = [0, 1, 2].map(|d| (p.position[d] - center[d]) as f64);
+            vector_length(v) as f32
+        })
+        .fold(0.0, f32::max);
+    (center, radius)
+}
+
+// column major, as the shader takes them
+fn apply(m: &[[f32; 4]; 4], v: [f32; 4]) -> [f32; 4] {
+    [0, 1, 2, 3].map(|r| (0..4).map(|c| m[c][r] * v[c]).sum())
+}
+
+fn multiply(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    b.map(|column| apply(a, column))
+}
+
+// how many pixels high a sphere placed by transform shows on a screen height pixels high,
+// through the view and perspective projection matrices
+pub fn projected_size(
+    sphere: ([f32; 3], f32),
+    transform: &[[f32; 4]; 4],
+    view: &[[f32; 4]; 4],
+    proj: &[[f32; 4]; 4],
+    height: u32,
+) -> f32 {
+    let eye = multiply(view, transform);
+    let (center, radius) = sphere;
+    let c = apply(&eye, [center[0], center[1], center[2], 1.0]);
+
+    // the largest stretch of the placement, so the sphere is never taken for smaller than it is
+    let stretch = (0..3)
+        .map(|d| (eye[d][0].powi(2) + eye[d][1].powi(2) + eye[d][2].powi(2)).sqrt())
+        .fold(0.0, f32::max);
+    let depth = -c[2];
+    let r = radius * stretch;
+    if depth <= r {
+        return f32::MAX; // the eye is inside or right at it
+    }
+    r / depth * proj[1][1].abs() * height as f32
+}
+
+// the level to draw at a projected size, out of levels
+pub fn level_for(pixels: f32, levels: usize) -> usize {
+    if pixels >= FULL_DETAIL_PIXELS || levels < 2 {
+        return 0;
+    }
+    let level = (FULL_DETAIL_PIXELS / pixels.max(f32::MIN_POSITIVE)).log2() as usize;
+    level.min(levels - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magma_ocean::{magma, petrify};
+    use crate::shapes::random_profile;
+    use crate::validation::validate;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn petrified(seed: u64) -> Stone {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let profile = random_profile(&mut rng);
+        petrify(magma(2, 10.0, &mut rng), &profile, &mut rng)
+    }
+
+    #[test]
+    fn simplified_stones_keep_to_their_target_and_stay_closed() {
+        for seed in 0..10 {
+            let stone = petrified(seed);
+            let triangles = stone.indices.len() / 3;
+            for target in [triangles / 2, triangles / 8, 20] {
+                let simple = simplify(&stone, target);
+                let left = simple.indices.len() / 3;
+                // an edge collapse takes away two triangles at a time
+                assert!(
+                    left <= target && left + 2 >= target,
+                    "{} for {}",
+                    left,
+                    target
+                );
+                let report = validate(&simple);
+                assert!(
+                    report.is_valid(),
+                    "seed {}, {} triangles: {}",
+                    seed,
+                    left,
+                    report
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn levels_halve_until_they_stop_getting_coarser() {
+        let levels = levels(petrified(1), 10);
+        assert!(levels.len() > 2);
+        for pair in levels.windows(2) {
+            let (finer, coarser) = (pair[0].indices.len() / 3, pair[1].indices.len() / 3);
+            assert!(coarser <= finer / 2 && coarser >= MIN_TRIANGLES);
+        }
+    }
+
+    #[test]
+    fn farther_stones_get_coarser_levels() {
+        let identity = [0, 1, 2, 3].map(|c| [0, 1, 2, 3].map(|r| if r == c { 1.0 } else { 0.0 }));
+        let mut previous = (f32::MAX, 0);
+        for step in 2..200 {
+            // looking down -z at a unit ball ever farther away
+            let mut transform = identity;
+            transform[3][2] = -(step as f32);
+            let pixels = projected_size(([0.0; 3], 1.0), &transform, &identity, &identity, 600);
+            let level = level_for(pixels, 6);
+
+            assert!(pixels < previous.0);
+            assert!(level >= previous.1);
+            previous = (pixels, level);
+        }
+        assert_eq!(level_for(f32::MAX, 6), 0);
+        assert_eq!(previous.1, 5);
+    }
+}
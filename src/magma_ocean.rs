@@ -1,12 +1,25 @@
 use rand::Rng;
+use std::f32::consts::PI;
 
 use crate::f32_3::{
-    angle_360_of, angular_difference, average_f32_3, dd_f32_3, find_points_normal, gen_f32_3,
-    gen_rthgnl_f32_3, mltply_f32_3, nrmlz_f32_3, sbtr_f32_3, vector_length,
+    angle_360_of, angular_difference, average_f32_3, dd_f32_3, dot_product, find_orthogonal_f32_3,
+    find_points_normal, gen_f32_3, gen_rthgnl_f32_3, mltply_f32_3, nrmlz_f32_3, sbtr_f32_3,
+    vector_length,
 };
+use crate::f64_3::{
+    dd_f64_3, dot_f64_3, mltply_f64_3, sbtr_f64_3, vector_length as vector_length_f64,
+};
+use crate::hull::{convex_hull, hull_stone, ring_ordered};
+use crate::normals::{orient_triangles, smooth_normals, Weighting};
 use crate::positions::{create_points_on_cross_section, sort_positions_by_angle, Normal, Position};
-use crate::shapes::{f32_3_dots_collinear, rotational_distance_function_sine, spherical_progress};
+use crate::shapes::{
+    f32_3_dots_collinear, spherical_progress, AxisProfile, Blend, Circle, CrossSectionProfile,
+    Fourier, Harmonic,
+};
 use crate::u_modular::{modular_difference_in_range, modular_offset_in_range};
+use crate::validation::{validate, CHECK_STONES};
+
+use std::sync::atomic::Ordering;
 
 #[derive(Debug)]
 pub struct Magma {
@@ -14,16 +27,14 @@ pub struct Magma {
     indices: Vec<u32>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Stone {
     pub positions: Vec<Position>,
     pub normals: Vec<Normal>,
     pub indices: Vec<u32>,
 }
 
-pub fn magma(flow: u32, scale: f32) -> Magma {
-    let mut rng = rand::thread_rng();
-
+pub fn magma<R: Rng + ?Sized>(flow: u32, scale: f32, rng: &mut R) -> Magma {
     let mut lava_flow = Magma {
         positions: vec![],
         indices: vec![],
@@ -33,7 +44,7 @@ pub fn magma(flow: u32, scale: f32) -> Magma {
     let mut cbase = -2.5 * scale;
     for i in 1..=flow {
         lava_flow.positions.push(Position {
-            position: gen_f32_3(cbase, base, &mut rng),
+            position: gen_f32_3(cbase, base, rng),
         });
         cbase = cbase + 5.0 * base;
 
@@ -51,9 +62,31 @@ pub fn magma(flow: u32, scale: f32) -> Magma {
     return lava_flow;
 }
 
-pub fn petrify(flow: Magma) -> Stone {
+// a stone with no flow to follow, the hull of points scattered through a ball of the size;
+// the more points, the rounder, and nothing at all without a size
+pub fn pebble<R: Rng + ?Sized>(points: usize, size: f32, rng: &mut R) -> Stone {
+    if size.is_nan() || size <= 0.0 {
+        return Stone {
+            positions: vec![],
+            normals: vec![],
+            indices: vec![],
+        };
+    }
+
+    let mut scattered = vec![];
+    while scattered.len() < points.max(4) {
+        let p = gen_f32_3(0.0, size, rng);
+        if vector_length(p) <= size {
+            scattered.push(Position { position: p });
+        }
+    }
+    hull_stone(&scattered)
+}
+
+// the profile shapes the cross sections, scaled to the stone's size plane by plane
+pub fn petrify<R: Rng + ?Sized>(flow: Magma, profile: &AxisProfile, rng: &mut R) -> Stone {
     if flow.positions.len() > 2 {
-        return petrify_flow(flow);
+        return petrify_flow(flow, profile, rng);
     };
 
     let mut stone = Stone {
@@ -62,14 +95,13 @@ pub fn petrify(flow: Magma) -> Stone {
         indices: vec![],
     };
 
-    let mut rng = rand::thread_rng();
     let points_diff = sbtr_f32_3(flow.positions[1].position, flow.positions[0].position);
     let planes_normal: [f32; 3] = nrmlz_f32_3(points_diff);
     let planes_number = 30; // rng.gen_range(40..42);
     let f__max_points = 30.0; // rng.gen_range(40.0..42.0);
     let max_points = f__max_points;
     let mut points_of_plane: u32 = 3;
-    let reference_orthogonal = gen_rthgnl_f32_3(planes_normal, &mut rng);
+    let reference_orthogonal = gen_rthgnl_f32_3(planes_normal, rng);
     let mut pln = 0;
 
     let planes_points = f32_3_dots_collinear(
@@ -84,19 +116,12 @@ pub fn petrify(flow: Magma) -> Stone {
         // println!("plane: {:#?}", plane_point);
         // vector_length(points_diff) / 2.0 * (PI * pln as f32 / planes_points.len() as f32).sin()
 
-        let rotational_arguments_vector = vec![
-            spherical_progress(
-                vector_length(points_diff),
-                pln as f32,
-                planes_number as f32 - 1.0,
-            ),
-            0.0,
-            0.0,
-            0.0,
-        ]; // f_const: f32,
-           // f_multiplier: f32,
-           // x_const: f32,
-           // x_multiplier: f32,
+        let radius = spherical_progress(
+            vector_length(points_diff),
+            pln as f32,
+            planes_number as f32 - 1.0,
+        );
+        let section = profile.at(pln, planes_number);
 
         let mut plane = Stone {
             positions: vec![],
@@ -105,13 +130,12 @@ pub fn petrify(flow: Magma) -> Stone {
         };
 
         plane.positions = create_points_on_cross_section(
-            rotational_distance_function_sine,
-            rotational_arguments_vector.clone(),
+            &|angle: f32| radius * section.distance(angle),
             reference_orthogonal,
             planes_normal,
             *plane_point,
             points_of_plane, //points_number
-            &mut rng,
+            rng,
         );
 
         points_of_plane = plane.positions.len() as u32;
@@ -122,23 +146,17 @@ pub fn petrify(flow: Magma) -> Stone {
             planes_normal,
             &mut plane.positions,
         );
-
-        let mut normal_origin = *plane_point;
-        if pln == 0 {
-            normal_origin = planes_points[1];
-        }
-        if pln == planes_number - 1 {
-            normal_origin = planes_points[(planes_number - 2) as usize];
+        if cfg!(debug_assertions)
+            && CHECK_STONES.load(Ordering::Relaxed)
+            && !ring_ordered(&plane.positions, planes_normal)
+        {
+            eprintln!("petrified a ring out of order at plane {}", pln);
         }
 
         for i in 0..points_of_plane {
             stone.positions.push(Position {
                 position: plane.positions[i as usize].position,
             });
-            let normal = //nrmlz_f32_3(gen_f32_3(0.0, 1.0, &mut rng));
-            // find_points_normal(plane.positions[(i as usize)].position, *plane_point);
-            find_points_normal(plane.positions[i as usize].position, normal_origin);
-            stone.normals.push(Normal { normal: normal });
         }
 
         if previous_plane[2] == 0 {
@@ -194,15 +212,358 @@ pub fn petrify(flow: Magma) -> Stone {
         };
     }
 
-    return stone;
+    // the rings are stitched in either direction, the normals need them all facing out
+    orient_triangles(&mut stone);
+    smooth_normals(&mut stone, Weighting::Angle, None);
+
+    return checked(stone);
+}
+
+// with --validate, debug builds report what is wrong with every stone they petrify
+fn checked(stone: Stone) -> Stone {
+    if cfg!(debug_assertions) && CHECK_STONES.load(Ordering::Relaxed) {
+        let report = validate(&stone);
+        if !report.is_valid() {
+            eprintln!("petrified a stone with {}", report);
+        }
+    }
+    stone
 }
 
-pub fn petrify_flow(_flow: Magma) -> Stone {
-    return Stone {
+// every edge of the flow becomes a tube, leaves end in a rounded tip and the open rings
+// where tubes meet at a node are joined by the convex hull around them
+pub fn petrify_flow<R: Rng + ?Sized>(flow: Magma, profile: &AxisProfile, rng: &mut R) -> Stone {
+    let mut stone = Stone {
         positions: vec![],
         normals: vec![],
         indices: vec![],
     };
+
+    let nodes: Vec<[f32; 3]> = flow.positions.iter().map(|p| p.position).collect();
+    let edges: Vec<[usize; 2]> = flow
+        .indices
+        .chunks(2)
+        .map(|e| [e[0] as usize, e[1] as usize])
+        .collect();
+
+    // unit directions of the edges leaving every node
+    let mut leaving: Vec<Vec<[f32; 3]>> = vec![vec![]; nodes.len()];
+    let mut shortest = vec![f32::MAX; nodes.len()];
+    for e in &edges {
+        let axis = sbtr_f32_3(nodes[e[1]], nodes[e[0]]);
+        leaving[e[0]].push(nrmlz_f32_3(axis));
+        leaving[e[1]].push(nrmlz_f32_3(mltply_f32_3(axis, -1.0)));
+        for n in e {
+            shortest[*n] = shortest[*n].min(vector_length(axis));
+        }
+    }
+
+    // how far from its node a ring sits and how wide it is; between edges at a tight angle
+    // the rings get narrower, so that each of them lies beyond the planes of all the others
+    let mut ring_distance = vec![0.0; nodes.len()];
+    let mut ring_radius = vec![0.0; nodes.len()];
+    for n in 0..nodes.len() {
+        let radius = TUBE_RADIUS * shortest[n];
+        if leaving[n].len() < 2 {
+            ring_distance[n] = radius;
+            ring_radius[n] = radius;
+            continue;
+        }
+
+        let mut tightest = PI;
+        for (i, a) in leaving[n].iter().enumerate() {
+            for b in &leaving[n][i + 1..] {
+                tightest = tightest.min(dot_product(*a, *b).clamp(-1.0, 1.0).acos());
+            }
+        }
+
+        ring_distance[n] = JUNCTION_DISTANCE * shortest[n];
+        // wide angles leave all the room there is, kept short of where tan runs off
+        let half = (tightest / 2.0).min(0.45 * PI);
+        ring_radius[n] = radius.min(0.7 * ring_distance[n] * half.tan());
+    }
+
+    // the open rings around every node
+    let mut junctions: Vec<Vec<Joint>> = vec![vec![]; nodes.len()];
+
+    for e in &edges {
+        let (a, b) = (e[0], e[1]);
+        let axis = sbtr_f32_3(nodes[b], nodes[a]);
+        let planes_normal = nrmlz_f32_3(axis);
+        let reference_orthogonal = gen_rthgnl_f32_3(planes_normal, rng);
+
+        let start = dd_f32_3(nodes[a], mltply_f32_3(planes_normal, ring_distance[a]));
+        let end = sbtr_f32_3(nodes[b], mltply_f32_3(planes_normal, ring_distance[b]));
+
+        let mut rings: Vec<Vec<u32>> = vec![];
+
+        // a leaf ends in half a sphere before the first ring of the tube
+        let mut first_tip = None;
+        if leaving[a].len() == 1 {
+            first_tip = Some(push_tip(&mut stone, nodes[a]));
+            for k in (1..=TIP_RINGS).rev() {
+                let angle = 0.5 * PI * k as f32 / (TIP_RINGS + 1) as f32;
+                let center = sbtr_f32_3(
+                    start,
+                    mltply_f32_3(planes_normal, ring_radius[a] * angle.sin()),
+                );
+                rings.push(push_ring(
+                    &mut stone,
+                    center,
+                    planes_normal,
+                    reference_orthogonal,
+                    &Circle {
+                        radius: ring_radius[a] * angle.cos(),
+                    },
+                ));
+            }
+        }
+
+        // the tube swells and gets lumpy in between, its ends stay round for the joints
+        let swell = rng.gen_range(0.0..0.5);
+        let lumps = rng.gen_range(0.0..0.15);
+        let lump_phase = rng.gen_range(0.0..2.0 * PI);
+        let lump_frequency = rng.gen_range(2..5);
+
+        let span = vector_length(sbtr_f32_3(end, start));
+        let spacing = 0.25 * (ring_radius[a] + ring_radius[b]);
+        let planes_number = ((span / spacing) as u32).clamp(1, 30);
+        let planes_points = f32_3_dots_collinear(start, end, planes_number);
+
+        for (pln, plane_point) in planes_points.iter().enumerate() {
+            let f = pln as f32 / planes_number as f32;
+            let bulge = (PI * f).sin();
+            let radius = (ring_radius[a] * (1.0 - f) + ring_radius[b] * f) * (1.0 + swell * bulge);
+            let shape = Blend {
+                from: &Circle { radius: 1.0 },
+                to: &profile.at(pln as u32, planes_number),
+                f: bulge,
+            };
+            let lumpy = Fourier {
+                radius: 0.0,
+                harmonics: vec![Harmonic {
+                    frequency: lump_frequency,
+                    amplitude: radius * lumps * bulge,
+                    phase: lump_phase,
+                }],
+            };
+            rings.push(push_ring(
+                &mut stone,
+                *plane_point,
+                planes_normal,
+                reference_orthogonal,
+                &|angle: f32| radius * shape.distance(angle) + lumpy.distance(angle),
+            ));
+        }
+        rings.push(push_ring(
+            &mut stone,
+            end,
+            planes_normal,
+            reference_orthogonal,
+            &Circle {
+                radius: ring_radius[b],
+            },
+        ));
+
+        let mut last_tip = None;
+        if leaving[b].len() == 1 {
+            for k in 1..=TIP_RINGS {
+                let angle = 0.5 * PI * k as f32 / (TIP_RINGS + 1) as f32;
+                let center = dd_f32_3(
+                    end,
+                    mltply_f32_3(planes_normal, ring_radius[b] * angle.sin()),
+                );
+                rings.push(push_ring(
+                    &mut stone,
+                    center,
+                    planes_normal,
+                    reference_orthogonal,
+                    &Circle {
+                        radius: ring_radius[b] * angle.cos(),
+                    },
+                ));
+            }
+            last_tip = Some(push_tip(&mut stone, nodes[b]));
+        }
+
+        for r in 1..rings.len() {
+            stitch_rings(&mut stone, &rings[r - 1], &rings[r]);
+        }
+
+        match first_tip {
+            Some(tip) => stitch_tip(&mut stone, tip, &rings[0], true),
+            None => junctions[a].push(joint(
+                &rings[0],
+                start,
+                planes_normal,
+                reference_orthogonal,
+                ring_radius[a],
+                1.0,
+            )),
+        }
+        match last_tip {
+            Some(tip) => stitch_tip(&mut stone, tip, &rings[rings.len() - 1], false),
+            None => junctions[b].push(joint(
+                &rings[rings.len() - 1],
+                end,
+                planes_normal,
+                reference_orthogonal,
+                ring_radius[b],
+                -1.0,
+            )),
+        }
+    }
+
+    for n in 0..nodes.len() {
+        if junctions[n].len() > 1 {
+            join_rings(&mut stone, ring_radius[n], &junctions[n]);
+        }
+    }
+
+    // the hulls at the joints come out facing whichever way, like the rings of petrify
+    orient_triangles(&mut stone);
+    smooth_normals(&mut stone, Weighting::Angle, None);
+    checked(stone)
+}
+
+static TUBE_RADIUS: f32 = 0.25; // of the shortest edge at a node
+static JUNCTION_DISTANCE: f32 = 0.3; // of the shortest edge at a node
+static TIP_RINGS: u32 = 4;
+static RING_POINTS: u32 = 16;
+
+// evenly spaced points of a cross section, counterclockwise seen along the planes normal
+fn push_ring(
+    stone: &mut Stone,
+    plane_point: [f32; 3],
+    planes_normal: [f32; 3],
+    reference_orthogonal: [f32; 3],
+    profile: &dyn CrossSectionProfile,
+) -> Vec<u32> {
+    let second_orthogonal = find_orthogonal_f32_3(planes_normal, reference_orthogonal);
+
+    let mut ring = vec![];
+    for i in 0..RING_POINTS {
+        let angle = 2.0 * PI * i as f32 / RING_POINTS as f32;
+        let direction = dd_f32_3(
+            mltply_f32_3(reference_orthogonal, angle.cos()),
+            mltply_f32_3(second_orthogonal, angle.sin()),
+        );
+        let distance = profile.distance(angle);
+        let position = dd_f32_3(plane_point, mltply_f32_3(direction, distance));
+
+        ring.push(stone.positions.len() as u32);
+        stone.positions.push(Position { position });
+    }
+
+    ring
+}
+
+fn push_tip(stone: &mut Stone, tip: [f32; 3]) -> u32 {
+    stone.positions.push(Position { position: tip });
+    (stone.positions.len() - 1) as u32
+}
+
+// the band between two rings of the same tube, the second one further along its axis
+fn stitch_rings(stone: &mut Stone, first: &[u32], second: &[u32]) {
+    for i in 0..first.len() {
+        let j = (i + 1) % first.len();
+        stone.indices.extend([first[i], first[j], second[i]]);
+        stone.indices.extend([first[j], second[j], second[i]]);
+    }
+}
+
+// the fan closing a ring at a tip, leading when the tip comes before the ring along the axis
+fn stitch_tip(stone: &mut Stone, tip: u32, ring: &[u32], leading: bool) {
+    for i in 0..ring.len() {
+        let j = (i + 1) % ring.len();
+        if leading {
+            stone.indices.extend([tip, ring[j], ring[i]]);
+        } else {
+            stone.indices.extend([tip, ring[i], ring[j]]);
+        }
+    }
+}
+
+// an open ring where a tube meets a node, its points once more at full precision
+// so that the hull around the node comes out closed even between edges at tight angles
+#[derive(Clone)]
+struct Joint {
+    ring: Vec<u32>,
+    points: Vec<[f64; 3]>,
+    center: [f64; 3],
+    away: [f64; 3], // from the node towards the tube
+}
+
+fn joint(
+    ring: &[u32],
+    plane_point: [f32; 3],
+    planes_normal: [f32; 3],
+    reference_orthogonal: [f32; 3],
+    radius: f32,
+    side: f64,
+) -> Joint {
+    let as_f64 = |p: [f32; 3]| [p[0] as f64, p[1] as f64, p[2] as f64];
+    let center = as_f64(plane_point);
+    let first_orthogonal = as_f64(reference_orthogonal);
+    let second_orthogonal = as_f64(find_orthogonal_f32_3(planes_normal, reference_orthogonal));
+
+    // the same points push_ring placed
+    let mut points = vec![];
+    for i in 0..RING_POINTS {
+        let angle = 2.0 * std::f64::consts::PI * i as f64 / RING_POINTS as f64;
+        let direction = dd_f64_3(
+            mltply_f64_3(first_orthogonal, angle.cos()),
+            mltply_f64_3(second_orthogonal, angle.sin()),
+        );
+        points.push(dd_f64_3(center, mltply_f64_3(direction, radius as f64)));
+    }
+
+    Joint {
+        ring: ring.to_vec(),
+        points,
+        center,
+        away: mltply_f64_3(as_f64(planes_normal), side),
+    }
+}
+
+// covers the space between the rings at a node with their convex hull; a low cone over
+// every ring keeps the hull from closing the ring itself, and its faces are dropped again
+fn join_rings(stone: &mut Stone, radius: f32, joints: &[Joint]) {
+    // the cone must stay flat enough for the points of every other ring to remain below it
+    let edge_radius = radius as f64 * (std::f64::consts::PI / RING_POINTS as f64).cos();
+    let mut points: Vec<[f64; 3]> = vec![];
+    for (k, j) in joints.iter().enumerate() {
+        let mut height = radius as f64;
+        for (l, other) in joints.iter().enumerate() {
+            if k == l {
+                continue;
+            }
+            for p in &other.points {
+                let relative = sbtr_f64_3(*p, j.center);
+                let along = dot_f64_3(relative, j.away);
+                let across = vector_length_f64(sbtr_f64_3(relative, mltply_f64_3(j.away, along)));
+                if across > edge_radius {
+                    height = height.min(0.5 * edge_radius * -along / (across - edge_radius));
+                }
+            }
+        }
+        points.push(dd_f64_3(j.center, mltply_f64_3(j.away, height)));
+    }
+
+    // apexes go in first, so no face ever spans a ring by itself while the hull grows
+    let apexes = points.len();
+    let mut vertices: Vec<u32> = vec![];
+    for j in joints {
+        points.extend(j.points.iter());
+        vertices.extend(j.ring.iter());
+    }
+
+    for face in convex_hull(&points) {
+        if face.iter().any(|i| *i < apexes) {
+            continue;
+        }
+        stone.indices.extend(face.map(|i| vertices[i - apexes]));
+    }
 }
 
 pub fn find_indices_double_circle(
@@ -375,32 +736,4 @@ pub fn find_indices_double_circle(
         index_double_saved = k;
         index_single_saved = a_min_dex;
     }
-
-    // debug
-    let mut debug = false;
-
-    if points_of_single_plane + points_of_double_plane > triangle_counter {
-        println!(
-            "Added less triangles ({}) than necessary ({})",
-            triangle_counter,
-            points_of_single_plane + points_of_double_plane
-        );
-
-        debug = true;
-    }
-    if points_of_single_plane + points_of_double_plane < triangle_counter {
-        println!(
-            "Added *****more***** triangles ({}) than necessary ({})",
-            triangle_counter,
-            points_of_single_plane + points_of_double_plane
-        );
-
-        debug = true;
-    }
-
-    if debug {
-        println!("Further Info 1{:#?}", single_vertex_plane,);
-
-        println!("Further Info 2{:#?}", double_vertex_plane,);
-    }
 }
@@ -15,19 +15,69 @@ mod f32_3;
 use f32_3::gen_f32_3;
 
 mod f64_3;
-use f64_3::{gen_f64_3, mltply_f64_3, nrmlz_f64_3};
+use f64_3::{dd_f64_3, gen_f64_3, mltply_f64_3, nrmlz_f64_3, sbtr_f64_3};
 
 mod positions;
-use positions::{Normal, Position};
+use positions::{Normal, Placement, Position};
 
 mod shapes;
 mod u_modular;
 
+mod integrator;
+
+mod distribution;
+mod erosion;
+use erosion::{erode_hydraulic, erode_thermal, heightfield_stone, terrain, Hydraulic, Thermal};
+mod hull;
+mod noise;
+use noise::{noise, Displacement, Kind};
+mod normals;
+mod octree;
+mod property;
+
+mod lod;
+use lod::{level_for, projected_size};
+
+mod collision;
 mod magma_ocean;
+mod measure;
+mod rigid;
+use collision::{collide_bodies, collide_components, collide_components_bodies, Material};
 use magma_ocean::Stone;
+use rigid::{apply_force, apply_impulse, placed_bodies, rigid_body, step, RigidBody};
+
+mod mesh_cache;
+use mesh_cache::{
+    generated, instances, mesh, offset, placed_stones, Mesh, MeshKey, Placed, LEVELS_OF_DETAIL,
+    MESH_SEED, VARIANTS,
+};
 
 mod anomaly;
-use anomaly::{add_particle_by, e, progress, q, view, Anomaly, LS_F64, TS_F64};
+use anomaly::{add_particle_by, asset, e, progress, q, view, Anomaly, LS_F64};
+
+mod clock;
+use clock::planck_clock;
+
+mod diagnostics;
+use diagnostics::{diagnose, report};
+
+mod scheduler;
+use scheduler::scheduler;
+
+mod validation;
+use validation::CHECK_STONES;
+
+mod scene;
+use scene::{load_scene, save_scene};
+
+mod offscreen;
+use offscreen::{offscreen, render, save_png};
+
+mod stone_io;
+use stone_io::{asset_stone, write_stones};
+
+mod volume;
+use volume::{dual_contour, metaballs};
 
 mod moving_around;
 use moving_around::{
@@ -36,10 +86,7 @@ use moving_around::{
 
 use cgmath::{Matrix3, Matrix4, Point3, Rad, Vector3};
 
-// mod x;
-// use x::{Normal, Position, INDICES, NORMALS, POSITIONS};
-
-use std::{sync::Arc, time::Instant};
+use std::{collections::HashMap, sync::Arc, time::Instant};
 use vulkano::{
     buffer::{
         allocator::{SubbufferAllocator, SubbufferAllocatorCreateInfo},
@@ -91,8 +138,42 @@ use winit::{
     window::{Fullscreen, Window},
 };
 
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// the value following a command line flag
+fn argument(flag: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let i = args.iter().position(|a| a == flag)?;
+    match args.get(i + 1) {
+        Some(value) => Some(value.clone()),
+        None => fail(format!("{} needs a value", flag)),
+    }
+}
+
+// the value after a flag read as a number or a name, leaving with a message when it is neither
+fn parsed<T: std::str::FromStr>(flag: &str) -> Option<T>
+where
+    T::Err: std::fmt::Display,
+{
+    argument(flag).map(|value| {
+        value
+            .parse()
+            .unwrap_or_else(|e| fail(format!("{} {}: {}", flag, value, e)))
+    })
+}
+
+// what was asked for on the command line and could not be done ends the run, not a panic
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(2);
+}
+
+fn or_fail<T, E: std::fmt::Display>(result: Result<T, E>, what: &str) -> T {
+    result.unwrap_or_else(|e| fail(format!("{}: {}", what, e)))
+}
 
+#[derive(Clone)]
 pub struct Bv {
     pub v: Subbuffer<[Position]>,
     pub n: Subbuffer<[Normal]>,
@@ -110,38 +191,230 @@ fn main() {
         duration_since_epoch_nanos.group_with_nothing()
     );
 
-    let mut rng = rand::thread_rng();
+    // --seed <number> repeats the random setup and shapes of an earlier run
+    let seed = parsed::<u64>("--seed").unwrap_or_else(|| rand::thread_rng().gen());
+    println!("seed {}", seed);
+    let mut rng = StdRng::seed_from_u64(seed);
+    MESH_SEED.store(seed, std::sync::atomic::Ordering::Relaxed);
+
+    // --variants <number> sets how many different stones there are of every size
+    if let Some(v) = parsed("--variants") {
+        VARIANTS.store(v, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    // --levels <number> sets how many ever coarser levels of detail a mesh gets, 1 for none
+    if let Some(l) = parsed("--levels") {
+        LEVELS_OF_DETAIL.store(l, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    // --erode <file> wears down a landscape grown from the seed and writes it as a mesh to be
+    // looked at with --asset, --cells, --droplets and --slides size the grid and both erosions
+    if let Some(path) = argument("--erode") {
+        let cells: usize = parsed("--cells").unwrap_or(128);
+        let hills = Displacement {
+            kind: Kind::Fbm,
+            octaves: 5,
+            frequency: 0.04,
+            amplitude: 6.0,
+            lacunarity: 2.0,
+            gain: 0.5,
+        };
+        let mut field = terrain(cells, cells, 0.5, &noise(&mut rng), &hills);
+
+        let hydraulic = Hydraulic {
+            droplets: parsed("--droplets").unwrap_or(50000),
+            ..Default::default()
+        };
+        erode_hydraulic(&mut field, &hydraulic, &mut rng);
+        let thermal = Thermal {
+            iterations: parsed("--slides").unwrap_or(50),
+            ..Default::default()
+        };
+        erode_thermal(&mut field, &thermal);
+
+        or_fail(write_stones(&path, &[heightfield_stone(&field)]), &path);
+        return;
+    }
 
     // let mut stone = petrify(magma(2, 10.0));
     // let mut pebble = petrify(magma(2, 50.0));
-    let mut anom = Anomaly {
-        anomaly: vec![],
-        component: vec![],
-        force: vec![],
+    // --scene <file> starts from a saved scene instead of a random one
+    let mut anom = if let Some(path) = argument("--scene") {
+        or_fail(load_scene(&path), &path)
+    } else {
+        let mut anom = Anomaly {
+            anomaly: vec![],
+            component: vec![],
+            force: vec![],
+            theta: 0.5,
+            integration: None,
+        };
+
+        let k = 10;
+
+        for _ in 0..k {
+            add_particle_by(
+                &mut anom,
+                e(
+                    gen_f32_3(0.0, 69.0, &mut rng),
+                    mltply_f64_3(nrmlz_f64_3(gen_f64_3(0.0, 10.0, &mut rng)), LS_F64),
+                    true,
+                ),
+            );
+            add_particle_by(
+                &mut anom,
+                q(
+                    gen_f32_3(0.0, 69.0, &mut rng),
+                    mltply_f64_3(nrmlz_f64_3(gen_f64_3(0.0, 10.0, &mut rng)), LS_F64),
+                    true,
+                    true,
+                    rng.gen_range(0..3),
+                    rng.gen_range(0..1),
+                ),
+            );
+        }
+
+        anom
     };
 
-    let k = 10;
+    // --asset <file.obj|file.ply> places a mesh at the center of the scene, next to the generated stones
+    if let Some(path) = argument("--asset") {
+        or_fail(asset_stone(&path), &path);
+        add_particle_by(&mut anom, asset([0.0, 0.0, 0.0], &path));
+    }
 
-    for _ in 0..k {
-        add_particle_by(
-            &mut anom,
-            e(
-                gen_f32_3(0.0, 69.0, &mut rng),
-                mltply_f64_3(nrmlz_f64_3(gen_f64_3(0.0, 10.0, &mut rng)), LS_F64),
-                true,
-            ),
-        );
-        add_particle_by(
-            &mut anom,
-            q(
-                gen_f32_3(0.0, 69.0, &mut rng),
-                mltply_f64_3(nrmlz_f64_3(gen_f64_3(0.0, 10.0, &mut rng)), LS_F64),
-                true,
-                true,
-                rng.gen_range(0..3),
-                rng.gen_range(0..1),
-            ),
-        );
+    // --save-scene <file> writes the starting scene out, to be shared or loaded again
+    if let Some(path) = argument("--save-scene") {
+        or_fail(save_scene(&path, &anom), &path);
+    }
+
+    // --validate makes debug builds report broken stones as they are petrified
+    if std::env::args().any(|a| a == "--validate") {
+        CHECK_STONES.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    // --sequential runs every traversal on the calling thread, for reproducible debugging
+    let workers = if std::env::args().any(|a| a == "--sequential") {
+        1
+    } else {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    };
+    let scheduler = scheduler(workers);
+
+    // one planck time per frame at sixty frames per second
+    let mut clock = planck_clock(1.0 / 60.0, 1.0, 4)
+        .unwrap_or_else(|| fail("no time scale for the frame time".to_string()));
+    // --integrator <ExplicitEuler|SemiImplicitEuler|VelocityVerlet|RungeKutta4> picks how it steps
+    // wherever the scene gives an anomaly no integration of its own
+    if let Some(name) = argument("--integrator") {
+        clock.integration = or_fail(serde_json::from_value(name.into()), "--integrator");
+    }
+
+    // --tumbling <number> throws that many rocks spinning through the scene, as rigid bodies
+    // each hanging on a spring from where it was thrown, tied to a corner of its hull
+    let mut bodies: Vec<RigidBody> = vec![];
+    let mut tethers: Vec<([f64; 3], [f64; 3])> = vec![];
+    if let Some(n) = parsed::<u32>("--tumbling") {
+        for _ in 0..n {
+            let key = generated(rng.gen_range(1.0..8.0), rng.gen());
+            let reach = mesh(&key).bounds.1 as f64;
+            let mut body = rigid_body(key, gen_f64_3(0.0, 69.0, &mut rng), 1.0);
+
+            // opposite pushes on either side set it turning, a third through the centroid drifting
+            let arm = mltply_f64_3(nrmlz_f64_3(gen_f64_3(0.0, 1.0, &mut rng)), reach);
+            let twist = mltply_f64_3(nrmlz_f64_3(gen_f64_3(0.0, 1.0, &mut rng)), 5.0 * body.mass);
+            let drift = mltply_f64_3(gen_f64_3(0.0, 1.0, &mut rng), body.mass);
+            let centroid = body.position;
+            apply_impulse(&mut body, twist, dd_f64_3(centroid, arm));
+            apply_impulse(
+                &mut body,
+                mltply_f64_3(twist, -1.0),
+                sbtr_f64_3(centroid, arm),
+            );
+            apply_impulse(&mut body, drift, centroid);
+            tethers.push((centroid, body.hull.first().copied().unwrap_or(body.center)));
+            bodies.push(body);
+        }
+    }
+
+    // --collide bounces particles and rocks off each other instead of letting them pass through,
+    // keeping --restitution of their approach and rubbing along each other by --friction
+    let collisions = if std::env::args().any(|a| a == "--collide") {
+        let default = Material::default();
+        Some(Material {
+            restitution: parsed("--restitution").unwrap_or(default.restitution),
+            friction: parsed("--friction").unwrap_or(default.friction),
+        })
+    } else {
+        None
+    };
+
+    // --diagnostics prints the momentum, energies, charge, center of mass and extent of the
+    // particles after every step, warning when what should be conserved drifts further than
+    // --drift from the start
+    let watch = if std::env::args().any(|a| a == "--diagnostics") {
+        Some((diagnose(&anom), parsed("--drift").unwrap_or(1e-3)))
+    } else {
+        None
+    };
+
+    // --export <file.obj|file.ply|file.gltf> writes the stones of the starting scene for other tools
+    if let Some(path) = argument("--export") {
+        let mut placed = view(&mut anom, &scheduler);
+        placed.extend(placed_bodies(&bodies));
+        or_fail(write_stones(&path, &placed_stones(&placed)), &path);
+    }
+
+    // --blob <file> writes the stones of the starting scene melted into one surface instead,
+    // each reaching --blob-radius far
+    if let Some(path) = argument("--blob") {
+        let radius: f32 = parsed("--blob-radius").unwrap_or(20.0);
+        let centers = view(&mut anom, &scheduler)
+            .iter()
+            .map(|p| offset(&p.transform))
+            .collect();
+        let balls = metaballs(centers, radius);
+        let (low, high) = balls.bounds();
+        let blob = dual_contour(&balls, low, high, radius / 6.0);
+        or_fail(write_stones(&path, &[blob]), &path);
+    }
+
+    // --headless <directory> renders --frames frames into png files there, without a window
+    if let Some(directory) = argument("--headless") {
+        let frames: u32 = parsed("--frames").unwrap_or(1);
+        let mut offscreen = offscreen([1280, 720])
+            .unwrap_or_else(|| fail("no vulkan device to render on".to_string()));
+
+        let mut contacts = 0;
+        for frame in 0..frames {
+            progress(&mut anom, &scheduler, &mut clock, 1.0 / 60.0);
+            step_bodies(&mut bodies, &tethers, 1.0 / 60.0);
+            if let Some(material) = &collisions {
+                contacts += collide_components(&mut anom, material).len();
+                contacts += collide_bodies(&mut bodies, material).len();
+                contacts += collide_components_bodies(&mut anom, &mut bodies, material).len();
+            }
+            if let Some((start, tolerance)) = &watch {
+                report(&anom, start, *tolerance);
+            }
+            let mut placed = view(&mut anom, &scheduler);
+            placed.extend(placed_bodies(&bodies));
+
+            let pixels = render(
+                &mut offscreen,
+                &placed,
+                [0.0, -1.0, 1.0],
+                [0.0, 0.0, 0.0],
+                [0.0, -1.0, 0.0],
+            );
+            let path = format!("{}/frame_{:05}.png", directory, frame);
+            or_fail(save_png(&path, offscreen.extent, &pixels), &path);
+        }
+        if collisions.is_some() {
+            println!("{} contacts over {} frames", contacts, frames);
+        }
+
+        return;
     }
 
     let ocl = oclock().cos();
@@ -308,6 +581,10 @@ fn main() {
 
     let mut previous_frame_end = Some(sync::now(device.clone()).boxed());
     let rotation_start = Instant::now();
+    let mut frame_start = Instant::now();
+
+    // the buffers of every mesh and level of detail drawn so far, uploaded once
+    let mut meshes: HashMap<(MeshKey, usize), Bv> = HashMap::new();
 
     let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
         device.clone(),
@@ -543,20 +820,21 @@ fn main() {
                     //
                     // move_positions(&mut stone.positions, [0.0, 0.0, 0.0]);
 
-                    progress(&mut anom, TS_F64);
-                    let get = view(&mut anom);
-
-                    let mut bvs: Vec<Bv> = vec![];
+                    let frame_time = frame_start.elapsed().as_secs_f64();
+                    frame_start = Instant::now();
 
-                    for mut g in get {
-                        let (vertex_buffer, normals_buffer, index_buffer) =
-                            load_buffers_short(&mut g, memory_allocator.clone());
-                        bvs.push(Bv {
-                            v: vertex_buffer,
-                            n: normals_buffer,
-                            i: index_buffer,
-                        });
+                    progress(&mut anom, &scheduler, &mut clock, frame_time);
+                    step_bodies(&mut bodies, &tethers, frame_time);
+                    if let Some(material) = &collisions {
+                        collide_components(&mut anom, material);
+                        collide_bodies(&mut bodies, material);
+                        collide_components_bodies(&mut anom, &mut bodies, material);
                     }
+                    if let Some((start, tolerance)) = &watch {
+                        report(&anom, start, *tolerance);
+                    }
+                    let mut placed = view(&mut anom, &scheduler);
+                    placed.extend(placed_bodies(&bodies));
 
                     //               let (vertex_buffer, normals_buffer, index_buffer) =
                     //                   load_buffers_short(&mut stone, memory_allocator.clone());
@@ -593,52 +871,35 @@ fn main() {
                         recreate_swapchain = false;
                     }
 
-                    let uniform_buffer_subbuffer = {
+                    let (uniform_buffer_subbuffer, bvs) = {
                         let elapsed = rotation_start.elapsed();
                         let mut rotation = 0.0;
                         if !rot_static {
                             rotation = elapsed.as_secs() as f64
                                 + elapsed.subsec_nanos() as f64 / 1_000_000_000.0;
                         }
-                        let rotation = Matrix3::from_angle_y(Rad(rotation as f32));
-
-                        // note: this teapot was meant for OpenGL where the origin is at the lower left
-                        //       instead the origin is at the upper left in Vulkan, so we reverse the Y axis
-                        let aspect_ratio =
-                            swapchain.image_extent()[0] as f32 / swapchain.image_extent()[1] as f32;
-                        let proj = cgmath::perspective(
-                            Rad(std::f32::consts::FRAC_PI_2),
-                            aspect_ratio,
-                            0.01,
-                            100.0,
-                        );
 
-                        let view = Matrix4::look_at_rh(
-                            Point3::new(
-                                view_point.position[0],
-                                view_point.position[1],
-                                view_point.position[2],
-                            ),
-                            Point3::new(center.position[0], center.position[1], center.position[2]),
-                            Vector3::new(
-                                up_direction.position[0],
-                                up_direction.position[1],
-                                up_direction.position[2],
-                            ),
+                        let uniform_data = uniform_data(
+                            swapchain.image_extent(),
+                            rotation as f32,
+                            view_point.position,
+                            center.position,
+                            up_direction.position,
                         );
 
-                        let scale = Matrix4::from_scale(0.01);
-
-                        let uniform_data = vs::Data {
-                            world: Matrix4::from(rotation).into(),
-                            view: (view * scale).into(),
-                            proj: proj.into(),
-                        };
+                        // the levels of detail depend on how big things show from where they are seen
+                        let bvs = instance_buffers(
+                            &placed,
+                            &mut meshes,
+                            memory_allocator.clone(),
+                            &uniform_data,
+                            swapchain.image_extent(),
+                        );
 
                         let subbuffer = uniform_buffer.allocate_sized().unwrap();
                         *subbuffer.write().unwrap() = uniform_data;
 
-                        subbuffer
+                        (subbuffer, bvs)
                     };
 
                     let layout = pipeline.layout().set_layouts().get(0).unwrap();
@@ -713,7 +974,7 @@ fn main() {
                             )
                             .unwrap();
 
-                        for x in bvs {
+                        for (x, placements) in bvs {
                             builder
                                 .begin_query(
                                     query_pool.clone(),
@@ -722,11 +983,14 @@ fn main() {
                                     // QueryControlFlags::PRECISE,
                                 )
                                 .unwrap()
-                                .bind_vertex_buffers(0, (x.v.clone(), x.n.clone()))
+                                .bind_vertex_buffers(
+                                    0,
+                                    (x.v.clone(), x.n.clone(), placements.clone()),
+                                )
                                 .unwrap()
                                 .bind_index_buffer(x.i.clone())
                                 .unwrap()
-                                .draw_indexed(x.i.len() as u32 as u32, 1, 0, 0, 0)
+                                .draw_indexed(x.i.len() as u32, placements.len() as u32, 0, 0, 0)
                                 .unwrap()
                                 .end_query(query_pool.clone(), 0)
                                 .unwrap();
@@ -821,9 +1085,13 @@ fn window_size_dependent_setup(
     // driver to optimize things, at the cost of slower window resizes.
     // https://computergraphics.stackexchange.com/questions/5742/vulkan-best-way-of-updating-pipeline-viewport
     let pipeline = {
-        let vertex_input_state = [Position::per_vertex(), Normal::per_vertex()]
-            .definition(&vs)
-            .unwrap();
+        let vertex_input_state = [
+            Position::per_vertex(),
+            Normal::per_vertex(),
+            Placement::per_instance(),
+        ]
+        .definition(&vs)
+        .unwrap();
         let stages = [
             PipelineShaderStageCreateInfo::new(vs),
             PipelineShaderStageCreateInfo::new(fs),
@@ -874,8 +1142,50 @@ fn window_size_dependent_setup(
     (pipeline, framebuffers)
 }
 
+/// The transformations of a frame, seen from view_point towards center.
+fn uniform_data(
+    image_extent: [u32; 2],
+    rotation: f32,
+    view_point: [f32; 3],
+    center: [f32; 3],
+    up_direction: [f32; 3],
+) -> vs::Data {
+    let rotation = Matrix3::from_angle_y(Rad(rotation));
+
+    // note: this teapot was meant for OpenGL where the origin is at the lower left
+    //       instead the origin is at the upper left in Vulkan, so we reverse the Y axis
+    let aspect_ratio = image_extent[0] as f32 / image_extent[1] as f32;
+    let proj = cgmath::perspective(Rad(std::f32::consts::FRAC_PI_2), aspect_ratio, 0.01, 100.0);
+
+    let view = Matrix4::look_at_rh(
+        Point3::new(view_point[0], view_point[1], view_point[2]),
+        Point3::new(center[0], center[1], center[2]),
+        Vector3::new(up_direction[0], up_direction[1], up_direction[2]),
+    );
+
+    let scale = Matrix4::from_scale(0.01);
+
+    // the scene turning is the eye going around it, every stone brings its own world matrix
+    vs::Data {
+        view: (view * scale * Matrix4::from(rotation)).into(),
+        proj: proj.into(),
+    }
+}
+
+// the tethered rocks pulled back by their springs, then moved on by time
+fn step_bodies(bodies: &mut [RigidBody], tethers: &[([f64; 3], [f64; 3])], time: f64) {
+    // per unit of mass, a swing takes about nine seconds
+    let stiffness = 0.5;
+    for (body, (anchor, corner)) in bodies.iter_mut().zip(tethers) {
+        let tied = body.world_point(*corner);
+        let pull = mltply_f64_3(sbtr_f64_3(*anchor, tied), stiffness * body.mass);
+        apply_force(body, pull, tied);
+        step(body, time);
+    }
+}
+
 fn load_buffers_short(
-    stone: &mut Stone,
+    stone: &Stone,
     memory_allocator: Arc<StandardMemoryAllocator>,
     //) -> (u32, u32, u32) {
 ) -> (Subbuffer<[Position]>, Subbuffer<[Normal]>, Subbuffer<[u32]>) {
@@ -938,3 +1248,63 @@ mod fs {
         path: "./src/frag.glsl",
     }
 }
+
+// the buffers of every mesh placed, at the level of detail its size on screen asks for,
+// uploading the ones not seen before, each with one buffer of its placements so it is drawn
+// once for all of them
+fn instance_buffers(
+    placed: &[Placed],
+    meshes: &mut HashMap<(MeshKey, usize), Bv>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    uniform_data: &vs::Data,
+    image_extent: [u32; 2],
+) -> Vec<(Bv, Subbuffer<[Placement]>)> {
+    let level = |p: &Placed, mesh: &Mesh| {
+        let pixels = projected_size(
+            mesh.bounds,
+            &p.transform,
+            &uniform_data.view,
+            &uniform_data.proj,
+            image_extent[1],
+        );
+        level_for(pixels, mesh.levels.len())
+    };
+
+    let mut ret = vec![];
+    for (key, transforms) in instances(placed, level) {
+        let bv = match meshes.get(&key) {
+            Some(bv) => bv.clone(),
+            None => {
+                // empty meshes get no buffers, there is nothing to draw
+                let mesh = mesh(&key.0);
+                let stone = &mesh.levels[key.1];
+                if stone.indices.is_empty() {
+                    continue;
+                }
+                let (v, n, i) = load_buffers_short(stone, memory_allocator.clone());
+                let bv = Bv { v, n, i };
+                meshes.insert(key, bv.clone());
+                bv
+            }
+        };
+
+        let placements = Buffer::from_iter(
+            memory_allocator.clone(),
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                    | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+                ..Default::default()
+            },
+            transforms
+                .into_iter()
+                .map(|transform| Placement { transform }),
+        )
+        .unwrap();
+        ret.push((bv, placements));
+    }
+    ret
+}
@@ -0,0 +1,288 @@
+use crate::f64_3::{cross_f64_3, dot_f64_3, mltply_f64_3, sbtr_f64_3, vector_length};
+use crate::magma_ocean::Stone;
+
+// how much there is of a closed stone and how it is spread, integrated over the tetrahedra
+// between the origin and every triangle, which the divergence theorem makes the whole solid;
+// the triangles should be counterclockwise seen from outside, or the volume comes out negative
+
+// what the solid of a stone comes to, for a density of one
+#[derive(Debug, Clone, Copy)]
+pub struct MassProperties {
+    pub volume: f64,
+    pub area: f64,
+    pub centroid: [f64; 3],
+    pub inertia: [[f64; 3]; 3], // about the centroid
+}
+
+fn corners(stone: &Stone) -> impl Iterator<Item = [[f64; 3]; 3]> + '_ {
+    stone
+        .indices
+        .chunks_exact(3)
+        .map(|t| [0, 1, 2].map(|d| stone.positions[t[d] as usize].position.map(|x| x as f64)))
+}
+
+// volume, first moment and second moment about the origin, the second as the integral of
+// x x^T over the solid
+fn moments(stone: &Stone) -> (f64, [f64; 3], [[f64; 3]; 3]) {
+    let mut volume = 0.0;
+    let mut first = [0.0; 3];
+    let mut second = [[0.0; 3]; 3];
+
+    for [a, b, c] in corners(stone) {
+        // six times the signed volume of the tetrahedron
+        let det = dot_f64_3(a, cross_f64_3(b, c));
+        volume += det / 6.0;
+        for d in 0..3 {
+            first[d] += det * (a[d] + b[d] + c[d]) / 24.0;
+        }
+        // over a tetrahedron with a corner at the origin, x_i x_j integrates to
+        // det / 120 * (sum of a_i a_j over the corners + (sum a_i)(sum a_j))
+        for i in 0..3 {
+            for j in 0..3 {
+                let corner_sum = a[i] * a[j] + b[i] * b[j] + c[i] * c[j];
+                let sums = (a[i] + b[i] + c[i]) * (a[j] + b[j] + c[j]);
+                second[i][j] += det * (corner_sum + sums) / 120.0;
+            }
+        }
+    }
+
+    (volume, first, second)
+}
+
+pub fn signed_volume(stone: &Stone) -> f64 {
+    corners(stone)
+        .map(|[a, b, c]| dot_f64_3(a, cross_f64_3(b, c)) / 6.0)
+        .sum()
+}
+
+pub fn surface_area(stone: &Stone) -> f64 {
+    corners(stone)
+        .map(|[a, b, c]| vector_length(cross_f64_3(sbtr_f64_3(b, a), sbtr_f64_3(c, a))) / 2.0)
+        .sum()
+}
+
+// the center of the volume, not of the vertices, which crowd wherever the surface is finer
+pub fn centroid(stone: &Stone) -> [f64; 3] {
+    let (volume, first, second) = moments(stone);
+    about_centroid(volume, first, second, 1.0).0
+}
+
+// the inertia tensor of the solid about its centroid, for an even density
+pub fn inertia_tensor(stone: &Stone, density: f64) -> [[f64; 3]; 3] {
+    let (volume, first, second) = moments(stone);
+    about_centroid(volume, first, second, density).1
+}
+
+// the centroid and the inertia about it that the moments about the origin come to
+fn about_centroid(
+    volume: f64,
+    first: [f64; 3],
+    second: [[f64; 3]; 3],
+    density: f64,
+) -> ([f64; 3], [[f64; 3]; 3]) {
+    if volume == 0.0 {
+        return ([0.0; 3], [[0.0; 3]; 3]);
+    }
+
+    // the second moment moved to the centroid, c c^T volume less
+    let c = mltply_f64_3(first, 1.0 / volume);
+    let mut centered = second;
+    for i in 0..3 {
+        for j in 0..3 {
+            centered[i][j] -= volume * c[i] * c[j];
+        }
+    }
+
+    let trace = centered[0][0] + centered[1][1] + centered[2][2];
+    let mut inertia = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            let diagonal = if i == j { trace } else { 0.0 };
+            inertia[i][j] = density * (diagonal - centered[i][j]);
+        }
+    }
+    (c, inertia)
+}
+
+// all the measures at once, which rigid bodies are made from
+pub fn mass_properties(stone: &Stone) -> MassProperties {
+    MassProperties {
+        volume: signed_volume(stone),
+        area: surface_area(stone),
+        centroid: centroid(stone),
+        inertia: inertia_tensor(stone, 1.0),
+    }
+}
+
+// the fixtures are shared with the tests of other modules working on stones
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::f64_3::{dd_f64_3, nrmlz_f64_3};
+    use crate::positions::Position;
+
+    use std::collections::HashMap;
+    use std::f64::consts::PI;
+
+    pub fn stone(positions: Vec<[f64; 3]>, indices: Vec<u32>) -> Stone {
+        Stone {
+            positions: positions
+                .iter()
+                .map(|p| Position {
+                    position: p.map(|x| x as f32),
+                })
+                .collect(),
+            normals: vec![],
+            indices,
+        }
+    }
+
+    // the cube from the origin to one, counterclockwise seen from outside
+    pub fn unit_cube() -> Stone {
+        let corners = (0..8)
+            .map(|i| [i & 1, (i >> 1) & 1, (i >> 2) & 1].map(|x| x as f64))
+            .collect();
+        let faces = [
+            [0, 2, 3, 1], // z = 0
+            [4, 5, 7, 6], // z = 1
+            [0, 1, 5, 4], // y = 0
+            [2, 6, 7, 3], // y = 1
+            [0, 4, 6, 2], // x = 0
+            [1, 3, 7, 5], // x = 1
+        ];
+        let indices = faces
+            .iter()
+            .flat_map(|[a, b, c, d]| [*a, *b, *c, *a, *c, *d])
+            .collect();
+        stone(corners, indices)
+    }
+
+    // an icosahedron split into four triangles per triangle for every subdivision, its
+    // corners pushed out onto the sphere
+    fn icosphere(center: [f64; 3], radius: f64, subdivisions: u32) -> Stone {
+        let t = (1.0 + 5.0_f64.sqrt()) / 2.0;
+        let mut points: Vec<[f64; 3]> = [
+            [-1.0, t, 0.0],
+            [1.0, t, 0.0],
+            [-1.0, -t, 0.0],
+            [1.0, -t, 0.0],
+            [0.0, -1.0, t],
+            [0.0, 1.0, t],
+            [0.0, -1.0, -t],
+            [0.0, 1.0, -t],
+            [t, 0.0, -1.0],
+            [t, 0.0, 1.0],
+            [-t, 0.0, -1.0],
+            [-t, 0.0, 1.0],
+        ]
+        .iter()
+        .map(|p| nrmlz_f64_3(*p))
+        .collect();
+        let mut triangles: Vec<[u32; 3]> = vec![
+            [0, 11, 5],
+            [0, 5, 1],
+            [0, 1, 7],
+            [0, 7, 10],
+            [0, 10, 11],
+            [1, 5, 9],
+            [5, 11, 4],
+            [11, 10, 2],
+            [10, 7, 6],
+            [7, 1, 8],
+            [3, 9, 4],
+            [3, 4, 2],
+            [3, 2, 6],
+            [3, 6, 8],
+            [3, 8, 9],
+            [4, 9, 5],
+            [2, 4, 11],
+            [6, 2, 10],
+            [8, 6, 7],
+            [9, 8, 1],
+        ];
+
+        for _ in 0..subdivisions {
+            let mut middles: HashMap<[u32; 2], u32> = HashMap::new();
+            let mut middle = |a: u32, b: u32, points: &mut Vec<[f64; 3]>| {
+                *middles.entry([a.min(b), a.max(b)]).or_insert_with(|| {
+                    let m = dd_f64_3(points[a as usize], points[b as usize]);
+                    points.push(nrmlz_f64_3(m));
+                    (points.len() - 1) as u32
+                })
+            };
+            let mut finer = vec![];
+            for [a, b, c] in triangles {
+                let ab = middle(a, b, &mut points);
+                let bc = middle(b, c, &mut points);
+                let ca = middle(c, a, &mut points);
+                finer.extend([[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]);
+            }
+            triangles = finer;
+        }
+
+        let positions = points
+            .iter()
+            .map(|p| dd_f64_3(center, mltply_f64_3(*p, radius)))
+            .collect();
+        stone(positions, triangles.concat())
+    }
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() <= tolerance * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn the_unit_cube_comes_to_what_it_should() {
+        let m = mass_properties(&unit_cube());
+        assert!(close(m.volume, 1.0, 1e-12), "{:?}", m);
+        assert!(close(m.area, 6.0, 1e-12), "{:?}", m);
+        for d in 0..3 {
+            assert!(close(m.centroid[d], 0.5, 1e-12), "{:?}", m);
+        }
+        for i in 0..3 {
+            for j in 0..3 {
+                let expected = if i == j { 1.0 / 6.0 } else { 0.0 };
+                assert!(close(m.inertia[i][j], expected, 1e-12), "{:?}", m);
+            }
+        }
+    }
+
+    #[test]
+    fn an_icosphere_comes_close_to_the_ball() {
+        let (center, radius) = ([1.0, -2.0, 3.0], 2.0);
+        let m = mass_properties(&icosphere(center, radius, 5));
+
+        let volume = 4.0 / 3.0 * PI * radius.powi(3);
+        assert!(
+            close(m.volume, volume, 1e-3),
+            "{} against {}",
+            m.volume,
+            volume
+        );
+        let area = 4.0 * PI * radius.powi(2);
+        assert!(close(m.area, area, 1e-3), "{} against {}", m.area, area);
+        for (c, expected) in m.centroid.iter().zip(center) {
+            assert!(close(*c, expected, 1e-6), "{:?}", m.centroid);
+        }
+        for i in 0..3 {
+            for j in 0..3 {
+                let expected = if i == j {
+                    0.4 * volume * radius.powi(2)
+                } else {
+                    0.0
+                };
+                assert!(close(m.inertia[i][j], expected, 2e-3), "{:?}", m.inertia);
+            }
+        }
+    }
+
+    #[test]
+    fn inside_out_gives_a_negative_volume() {
+        let mut cube = unit_cube();
+        for t in cube.indices.chunks_exact_mut(3) {
+            t.swap(1, 2);
+        }
+        assert!(close(signed_volume(&cube), -1.0, 1e-12));
+        assert!(close(centroid(&cube)[0], 0.5, 1e-12));
+    }
+}
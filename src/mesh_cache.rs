@@ -0,0 +1,240 @@
+use crate::f32_3::nrmlz_f32_3;
+use crate::lod::{bounding_sphere, levels};
+use crate::magma_ocean::{magma, petrify, Stone};
+use crate::noise::{displace, noise, random_displacement};
+use crate::positions::{Normal, Position};
+use crate::shapes::random_profile;
+use crate::stone_io::asset_stone;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+
+// stones are made once per size and variant and then only placed, so thousands of particles
+// cost a handful of meshes and keep their shapes from one frame to the next
+
+// how many different stones there are of every size
+pub static VARIANTS: AtomicU32 = AtomicU32::new(8);
+// the stones of a run follow from it, set from --seed
+pub static MESH_SEED: AtomicU64 = AtomicU64::new(0);
+// how many levels of detail every mesh gets at most, the first one being the mesh itself
+pub static LEVELS_OF_DETAIL: AtomicU32 = AtomicU32::new(4);
+
+// sizes within an eighth of a doubling of each other share their stones
+static CLASSES_PER_DOUBLING: f64 = 8.0;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MeshKey {
+    // a petrified stone, None for the massless
+    Generated { class: Option<i32>, variant: u32 },
+    // a mesh file
+    Asset(String),
+}
+
+// column by column, as the shader takes it
+pub type Transform = [[f32; 4]; 4];
+
+// one mesh placed somewhere
+#[derive(Debug, Clone)]
+pub struct Placed {
+    pub mesh: MeshKey,
+    pub transform: Transform,
+}
+
+// a mesh as made and coarser, with a sphere around it
+pub struct Mesh {
+    pub levels: Vec<Stone>,
+    pub bounds: ([f32; 3], f32),
+}
+
+static MESHES: LazyLock<Mutex<HashMap<MeshKey, Arc<Mesh>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+pub fn size_class(size: f64) -> Option<i32> {
+    if size > 0.0 {
+        Some((size.log2() * CLASSES_PER_DOUBLING).round() as i32)
+    } else {
+        None
+    }
+}
+
+pub fn class_size(class: Option<i32>) -> f32 {
+    match class {
+        Some(c) => 2.0_f64.powf(c as f64 / CLASSES_PER_DOUBLING) as f32,
+        None => 0.0,
+    }
+}
+
+// mixes a position in the tree of anomalies into a key, so every stone picks its variant
+// from where it sits and not from when it is viewed
+pub fn branch(path: u64, i: u64) -> u64 {
+    let mut z = path
+        ^ i.wrapping_add(0x9e3779b97f4a7c15)
+            .wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+pub fn generated(size: f64, path: u64) -> MeshKey {
+    MeshKey::Generated {
+        class: size_class(size),
+        variant: (path % VARIANTS.load(Ordering::Relaxed).max(1) as u64) as u32,
+    }
+}
+
+pub fn translation(offset: [f32; 3]) -> Transform {
+    [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [offset[0], offset[1], offset[2], 1.0],
+    ]
+}
+
+// where a transform puts the origin
+pub fn offset(transform: &Transform) -> [f32; 3] {
+    [transform[3][0], transform[3][1], transform[3][2]]
+}
+
+// the mesh of a key with its levels of detail, made the first time it is asked for
+pub fn mesh(key: &MeshKey) -> Arc<Mesh> {
+    if let Some(mesh) = MESHES.lock().unwrap().get(key) {
+        return mesh.clone();
+    }
+
+    let stone = match key {
+        // nothing to petrify without a size, the massless are not drawn
+        MeshKey::Generated { class: None, .. } => Stone {
+            positions: vec![],
+            normals: vec![],
+            indices: vec![],
+        },
+        MeshKey::Generated { class, variant } => {
+            let seed = branch(
+                branch(
+                    MESH_SEED.load(Ordering::Relaxed),
+                    class.unwrap_or(i32::MIN) as u64,
+                ),
+                *variant as u64,
+            );
+            let mut rng = StdRng::seed_from_u64(seed);
+            let size = class_size(*class);
+
+            let profile = random_profile(&mut rng);
+            let mut stone = petrify(magma(2, size, &mut rng), &profile, &mut rng);
+            let weathering = random_displacement(size, &mut rng);
+            displace(&mut stone, &noise(&mut rng), &weathering);
+            stone
+        }
+        // scenes check their assets when loading, one gone since then is drawn as nothing
+        MeshKey::Asset(path) => asset_stone(path).unwrap_or_else(|e| {
+            eprintln!("{}: {}", path, e);
+            Stone {
+                positions: vec![],
+                normals: vec![],
+                indices: vec![],
+            }
+        }),
+    };
+    let mesh = Arc::new(Mesh {
+        bounds: bounding_sphere(&stone),
+        levels: levels(
+            stone,
+            LEVELS_OF_DETAIL.load(Ordering::Relaxed).max(1) as usize,
+        ),
+    });
+
+    // made without the lock held, so another thread may have made it meanwhile
+    MESHES
+        .lock()
+        .unwrap()
+        .entry(key.clone())
+        .or_insert(mesh)
+        .clone()
+}
+
+// the placed meshes as stones of their own, for whatever does not draw instances
+pub fn placed_stones(placed: &[Placed]) -> Vec<Stone> {
+    placed
+        .iter()
+        .map(|p| {
+            let mesh = mesh(&p.mesh);
+            let stone = &mesh.levels[0];
+            let t = p.transform;
+            let apply = |v: [f32; 3], w: f32| {
+                let mut r = [0.0; 3];
+                for (d, x) in r.iter_mut().enumerate() {
+                    *x = t[0][d] * v[0] + t[1][d] * v[1] + t[2][d] * v[2] + t[3][d] * w;
+                }
+                r
+            };
+            Stone {
+                positions: stone
+                    .positions
+                    .iter()
+                    .map(|p| Position {
+                        position: apply(p.position, 1.0),
+                    })
+                    .collect(),
+                // fine for rotations and even scaling, which is all placing does
+                normals: stone
+                    .normals
+                    .iter()
+                    .map(|n| Normal {
+                        normal: nrmlz_f32_3(apply(n.normal, 0.0)),
+                    })
+                    .collect(),
+                indices: stone.indices.clone(),
+            }
+        })
+        .collect()
+}
+
+// placed meshes grouped by mesh and the level of detail picked for them, each with its
+// transforms, in the order they were first seen
+pub fn instances<F>(placed: &[Placed], level: F) -> Vec<((MeshKey, usize), Vec<Transform>)>
+where
+    F: Fn(&Placed, &Mesh) -> usize,
+{
+    let mut groups: HashMap<(MeshKey, usize), usize> = HashMap::new();
+    let mut ret: Vec<((MeshKey, usize), Vec<Transform>)> = vec![];
+    for p in placed {
+        let m = mesh(&p.mesh);
+        let key = (p.mesh.clone(), level(p, &m).min(m.levels.len() - 1));
+        let g = *groups.entry(key.clone()).or_insert_with(|| {
+            ret.push((key, vec![]));
+            ret.len() - 1
+        });
+        ret[g].1.push(p.transform);
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_massless_get_an_empty_mesh() {
+        let made = mesh(&generated(0.0, 3));
+        assert_eq!(made.levels.len(), 1);
+        assert!(made.levels[0].indices.is_empty());
+        assert_eq!(made.bounds.1, 0.0);
+    }
+
+    #[test]
+    fn a_key_is_made_once() {
+        let key = generated(3.0, 5);
+        assert!(Arc::ptr_eq(&mesh(&key), &mesh(&key)));
+        assert!(!mesh(&key).levels[0].indices.is_empty());
+    }
+
+    #[test]
+    fn a_missing_asset_gets_an_empty_mesh() {
+        let made = mesh(&MeshKey::Asset("no such stone.obj".to_string()));
+        assert!(made.levels[0].indices.is_empty());
+    }
+}
@@ -0,0 +1,280 @@
+use crate::f32_3::{dd_f32_3, dot_product, mltply_f32_3, nrmlz_f32_3, sbtr_f32_3, vector_length};
+use crate::magma_ocean::Stone;
+use crate::normals::{smooth_normals, Weighting};
+use crate::validation::welded;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+// seeded gradient and cellular noise over space, summed over octaves into the roughness
+// that weathers the surface of a stone
+
+pub struct Noise {
+    permutation: Vec<u8>, // a shuffle of 0..=255, twice over so lookups need no wrapping
+}
+
+pub fn noise<R: Rng + ?Sized>(rng: &mut R) -> Noise {
+    let mut p: Vec<u8> = (0..=255).collect();
+    p.shuffle(rng);
+    let mut permutation = p.clone();
+    permutation.extend(p);
+    Noise { permutation }
+}
+
+// edge midpoints of a cube, the gradients of improved perlin noise
+static GRADIENTS: [[f32; 3]; 12] = [
+    [1.0, 1.0, 0.0],
+    [-1.0, 1.0, 0.0],
+    [1.0, -1.0, 0.0],
+    [-1.0, -1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [-1.0, 0.0, 1.0],
+    [1.0, 0.0, -1.0],
+    [-1.0, 0.0, -1.0],
+    [0.0, 1.0, 1.0],
+    [0.0, -1.0, 1.0],
+    [0.0, 1.0, -1.0],
+    [0.0, -1.0, -1.0],
+];
+
+impl Noise {
+    fn hash(&self, cell: [i32; 3]) -> usize {
+        let p = &self.permutation;
+        let x = p[(cell[0] & 255) as usize] as usize;
+        let y = p[x + (cell[1] & 255) as usize] as usize;
+        p[y + (cell[2] & 255) as usize] as usize
+    }
+
+    // smooth gradient noise, roughly between -1 and 1, zero at every whole point
+    pub fn perlin(&self, p: [f32; 3]) -> f32 {
+        let floor = p.map(|x| x.floor());
+        let cell = floor.map(|x| x as i32);
+        let local = sbtr_f32_3(p, floor);
+        let fade = local.map(|t| t * t * t * (t * (t * 6.0 - 15.0) + 10.0));
+
+        let mut sum = 0.0;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let gradient = GRADIENTS[self.hash([
+                cell[0] + offset[0],
+                cell[1] + offset[1],
+                cell[2] + offset[2],
+            ]) % 12];
+            let towards = sbtr_f32_3(local, offset.map(|o| o as f32));
+
+            let mut weight = 1.0;
+            for d in 0..3 {
+                weight *= if offset[d] == 1 {
+                    fade[d]
+                } else {
+                    1.0 - fade[d]
+                };
+            }
+            sum += weight * dot_product(gradient, towards);
+        }
+        sum
+    }
+
+    // distance to the nearest of one random point per unit cell, between 0 and about 1
+    pub fn worley(&self, p: [f32; 3]) -> f32 {
+        let cell = p.map(|x| x.floor() as i32);
+
+        let mut nearest = f32::MAX;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let c = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                    let h = self.hash(c);
+                    let feature = [
+                        c[0] as f32 + self.permutation[h] as f32 / 256.0,
+                        c[1] as f32 + self.permutation[h + 1] as f32 / 256.0,
+                        c[2] as f32 + self.permutation[h + 2] as f32 / 256.0,
+                    ];
+                    nearest = nearest.min(vector_length(sbtr_f32_3(feature, p)));
+                }
+            }
+        }
+        nearest
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Fbm,    // rolling bumps
+    Ridged, // sharp crests, folded and layered looking
+    Worley, // pitted, cells sunk in around scattered points
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Displacement {
+    pub kind: Kind,
+    pub octaves: u32,
+    pub frequency: f32,  // of the first octave, per unit of space
+    pub amplitude: f32,  // of the first octave, in units of space
+    pub lacunarity: f32, // frequency multiplier from one octave to the next
+    pub gain: f32,       // amplitude multiplier from one octave to the next
+}
+
+// the noise of the kind summed over the octaves, in units of space
+pub fn fractal(noise: &Noise, displacement: &Displacement, p: [f32; 3]) -> f32 {
+    let mut frequency = displacement.frequency;
+    let mut amplitude = displacement.amplitude;
+    let mut sum = 0.0;
+    let mut weight = 1.0_f32;
+
+    for octave in 0..displacement.octaves {
+        // every octave reads the noise somewhere else, so their features do not line up
+        let q = dd_f32_3(mltply_f32_3(p, frequency), [octave as f32 * 31.7; 3]);
+        sum += amplitude
+            * match displacement.kind {
+                Kind::Fbm => noise.perlin(q),
+                Kind::Ridged => {
+                    // crests where the noise crosses zero, finer ones only along coarser crests
+                    let ridge = (1.0 - noise.perlin(q).abs()).powi(2) * weight;
+                    weight = (2.0 * ridge).clamp(0.0, 1.0);
+                    ridge
+                }
+                Kind::Worley => -noise.worley(q),
+            };
+
+        frequency *= displacement.lacunarity;
+        amplitude *= displacement.gain;
+    }
+
+    sum
+}
+
+// moves every vertex along its normal by the noise at its place, then smooths the normals again;
+// vertices sharing a place move together so seams stay closed
+pub fn displace(stone: &mut Stone, noise: &Noise, displacement: &Displacement) {
+    let places = welded(&stone.positions);
+
+    let mut place_normals = vec![[0.0; 3]; stone.positions.len()];
+    for (i, n) in stone.normals.iter().enumerate() {
+        let place = places[i] as usize;
+        place_normals[place] = dd_f32_3(place_normals[place], n.normal);
+    }
+
+    let offsets: Vec<[f32; 3]> = places
+        .iter()
+        .map(|place| {
+            let p = stone.positions[*place as usize].position;
+            mltply_f32_3(
+                nrmlz_f32_3(place_normals[*place as usize]),
+                fractal(noise, displacement, p),
+            )
+        })
+        .collect();
+    for (position, offset) in stone.positions.iter_mut().zip(offsets) {
+        position.position = dd_f32_3(position.position, offset);
+    }
+
+    smooth_normals(stone, Weighting::Angle, None);
+}
+
+// some weathering for a stone of about the given size, of a random kind
+pub fn random_displacement<R: Rng + ?Sized>(size: f32, rng: &mut R) -> Displacement {
+    Displacement {
+        kind: [Kind::Fbm, Kind::Ridged, Kind::Worley][rng.gen_range(0..3)],
+        octaves: rng.gen_range(3..6),
+        frequency: rng.gen_range(0.5..1.5) / size,
+        amplitude: rng.gen_range(0.05..0.2) * size,
+        lacunarity: 2.0,
+        gain: 0.5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::f32_3::cross_f32_3;
+    use crate::magma_ocean::{magma, petrify};
+    use crate::shapes::random_profile;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn displacement(kind: Kind) -> Displacement {
+        Displacement {
+            kind,
+            octaves: 4,
+            frequency: 0.3,
+            amplitude: 0.5,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    fn points() -> Vec<[f32; 3]> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..200)
+            .map(|_| [0, 1, 2].map(|_| rng.gen_range(-20.0..20.0)))
+            .collect()
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_noise() {
+        let a = noise(&mut StdRng::seed_from_u64(7));
+        let b = noise(&mut StdRng::seed_from_u64(7));
+        let other = noise(&mut StdRng::seed_from_u64(8));
+
+        for kind in [Kind::Fbm, Kind::Ridged, Kind::Worley] {
+            let d = displacement(kind);
+            let values =
+                |n: &Noise| -> Vec<f32> { points().iter().map(|p| fractal(n, &d, *p)).collect() };
+            assert_eq!(values(&a), values(&b));
+            assert_ne!(values(&a), values(&other));
+        }
+        let worley = |n: &Noise| -> Vec<f32> { points().iter().map(|p| n.worley(*p)).collect() };
+        assert_eq!(worley(&a), worley(&b));
+    }
+
+    #[test]
+    fn displace_moves_along_the_normals_within_the_amplitude() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for kind in [Kind::Fbm, Kind::Ridged, Kind::Worley] {
+            let profile = random_profile(&mut rng);
+            let before = petrify(magma(2, 10.0, &mut rng), &profile, &mut rng);
+            let d = displacement(kind);
+            let mut after = before.clone();
+            displace(&mut after, &noise(&mut rng), &d);
+
+            // every octave at its most, worley reaching about a cell's diagonal at worst
+            let most = match kind {
+                Kind::Fbm => 1.1,
+                Kind::Ridged => 1.0,
+                Kind::Worley => 3f32.sqrt(),
+            };
+            let bound: f32 = (0..d.octaves)
+                .map(|o| d.amplitude * d.gain.powi(o as i32) * most)
+                .sum();
+
+            let places = welded(&before.positions);
+            let mut moved = 0.0_f32;
+            for i in 0..before.positions.len() {
+                // the welded tips move along the normal of their place
+                if places.iter().filter(|p| **p == places[i]).count() > 1 {
+                    continue;
+                }
+                let offset = sbtr_f32_3(after.positions[i].position, before.positions[i].position);
+                let length = vector_length(offset);
+                assert!(
+                    length <= bound,
+                    "{:?} moved {} past {}",
+                    kind,
+                    length,
+                    bound
+                );
+                let sideways = vector_length(cross_f32_3(offset, before.normals[i].normal));
+                assert!(
+                    sideways <= 1e-3 * (1.0 + length),
+                    "{:?} moved {} sideways",
+                    kind,
+                    sideways
+                );
+                moved = moved.max(length);
+            }
+            assert!(moved > 0.0);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::f32_3::{
+    average_f32_3, cross_f32_3, dd_f32_3, dot_product, mltply_f32_3, nrmlz_f32_3, sbtr_f32_3,
+    vector_length,
+};
+use crate::magma_ocean::Stone;
+use crate::positions::Normal;
+use crate::validation::welded;
+
+use std::collections::HashMap;
+
+// vertex normals from the triangles of a stone, whatever made it, and the winding they rely on
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Weighting {
+    Area,  // big triangles count more
+    Angle, // triangles count by the angle of their corner at the vertex, whatever their size
+}
+
+// every vertex gets the weighted average of the faces around its place; with a crease angle
+// (radians) faces bent further than that from a corner's own face are left out of its average,
+// and vertices whose corners end up with different normals are split
+pub fn smooth_normals(stone: &mut Stone, weighting: Weighting, crease: Option<f32>) {
+    let places = welded(&stone.positions);
+
+    // unit face normal and weight of every corner, gathered by the place of the vertex
+    let mut corners: Vec<([f32; 3], f32)> = vec![];
+    let mut at_place: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (k, &i) in stone.indices.iter().enumerate() {
+        let t = k - k % 3;
+        let [a, b, c] = [0, 1, 2].map(|d| stone.positions[stone.indices[t + d] as usize].position);
+        let face = cross_f32_3(sbtr_f32_3(b, a), sbtr_f32_3(c, a));
+
+        let weight = match weighting {
+            Weighting::Area => vector_length(face),
+            Weighting::Angle => {
+                let corner = stone.positions[i as usize].position;
+                let others = [a, b, c]
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(d, _)| *d != k % 3)
+                    .map(|(_, p)| nrmlz_f32_3(sbtr_f32_3(p, corner)))
+                    .collect::<Vec<[f32; 3]>>();
+                dot_product(others[0], others[1]).clamp(-1.0, 1.0).acos()
+            }
+        };
+
+        corners.push((nrmlz_f32_3(face), weight));
+        at_place.entry(places[i as usize]).or_default().push(k);
+    }
+
+    let cos_crease = crease.map(|c| c.cos());
+    let corner_normal = |k: usize| {
+        let own = corners[k].0;
+        let mut sum = [0.0; 3];
+        for &d in &at_place[&places[stone.indices[k] as usize]] {
+            let (face, weight) = corners[d];
+            if cos_crease.is_none_or(|c| dot_product(own, face) >= c) {
+                sum = dd_f32_3(sum, mltply_f32_3(face, weight));
+            }
+        }
+        nrmlz_f32_3(sum)
+    };
+
+    if cos_crease.is_none() {
+        let mut normals = vec![[0.0; 3]; stone.positions.len()];
+        for k in 0..stone.indices.len() {
+            normals[stone.indices[k] as usize] = corner_normal(k);
+        }
+        stone.normals = normals
+            .into_iter()
+            .map(|normal| Normal { normal })
+            .collect();
+        return;
+    }
+
+    // a vertex for every different normal the corners of one vertex get
+    let mut split: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut indices = vec![];
+    for k in 0..stone.indices.len() {
+        let i = stone.indices[k];
+        let normal = corner_normal(k);
+        let vertex = *split
+            .entry((i, normal.map(f32::to_bits)))
+            .or_insert_with(|| {
+                positions.push(stone.positions[i as usize]);
+                normals.push(Normal { normal });
+                (positions.len() - 1) as u32
+            });
+        indices.push(vertex);
+    }
+
+    stone.positions = positions;
+    stone.normals = normals;
+    stone.indices = indices;
+}
+
+// turns triangles so neighbours walk their shared edges in opposite directions, and every
+// connected piece so it encloses a positive volume, counterclockwise seen from outside
+pub fn orient_triangles(stone: &mut Stone) {
+    let places = welded(&stone.positions);
+    let triangles = stone.indices.len() / 3;
+    let corners =
+        |t: usize, indices: &[u32]| [0, 1, 2].map(|d| places[indices[3 * t + d] as usize]);
+
+    // triangles with two corners at one place have no side to agree on and are left alone
+    let mut sharing: HashMap<[u32; 2], Vec<usize>> = HashMap::new();
+    for t in 0..triangles {
+        let [a, b, c] = corners(t, &stone.indices);
+        if a == b || b == c || c == a {
+            continue;
+        }
+        for e in [[a, b], [b, c], [c, a]] {
+            sharing
+                .entry([e[0].min(e[1]), e[0].max(e[1])])
+                .or_default()
+                .push(t);
+        }
+    }
+
+    let mut visited = vec![false; triangles];
+    for seed in 0..triangles {
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+
+        let mut piece = vec![seed];
+        let mut next = 0;
+        while next < piece.len() {
+            let t = piece[next];
+            next += 1;
+
+            let [a, b, c] = corners(t, &stone.indices);
+            for e in [[a, b], [b, c], [c, a]] {
+                let Some(neighbours) = sharing.get(&[e[0].min(e[1]), e[0].max(e[1])]) else {
+                    continue;
+                };
+                for &u in neighbours {
+                    if visited[u] {
+                        continue;
+                    }
+                    visited[u] = true;
+
+                    // the neighbour walks the edge the same way, so it is turned
+                    let [x, y, z] = corners(u, &stone.indices);
+                    if [[x, y], [y, z], [z, x]].contains(&e) {
+                        stone.indices.swap(3 * u + 1, 3 * u + 2);
+                    }
+                    piece.push(u);
+                }
+            }
+        }
+
+        // volume around the piece's own center, so open pieces far from the origin work too
+        let center = average_f32_3(
+            piece
+                .iter()
+                .flat_map(|t| corners(*t, &stone.indices))
+                .map(|i| stone.positions[i as usize].position)
+                .collect(),
+        );
+        let volume: f32 = piece
+            .iter()
+            .map(|t| {
+                let [a, b, c] = corners(*t, &stone.indices)
+                    .map(|i| sbtr_f32_3(stone.positions[i as usize].position, center));
+                dot_product(a, cross_f32_3(b, c))
+            })
+            .sum();
+        if volume < 0.0 {
+            for t in piece {
+                stone.indices.swap(3 * t + 1, 3 * t + 2);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::tests::unit_cube;
+
+    fn unit_face(stone: &Stone, t: usize) -> [f32; 3] {
+        let [a, b, c] =
+            [0, 1, 2].map(|d| stone.positions[stone.indices[3 * t + d] as usize].position);
+        nrmlz_f32_3(cross_f32_3(sbtr_f32_3(b, a), sbtr_f32_3(c, a)))
+    }
+
+    #[test]
+    fn a_creased_cube_gets_a_vertex_per_face_corner() {
+        let mut cube = unit_cube();
+        smooth_normals(&mut cube, Weighting::Angle, Some(30f32.to_radians()));
+
+        assert_eq!(cube.positions.len(), 24);
+        assert_eq!(cube.normals.len(), 24);
+        for t in 0..cube.indices.len() / 3 {
+            let face = unit_face(&cube, t);
+            // along one axis
+            assert_eq!(face.iter().filter(|x| x.abs() > 1e-6).count(), 1);
+            for d in 0..3 {
+                let normal = cube.normals[cube.indices[3 * t + d] as usize].normal;
+                assert!(
+                    dot_product(normal, face) > 1.0 - 1e-6,
+                    "{:?} {:?}",
+                    normal,
+                    face
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn a_smooth_cube_points_its_corners_outward() {
+        let mut cube = unit_cube();
+        smooth_normals(&mut cube, Weighting::Angle, None);
+
+        assert_eq!(cube.positions.len(), 8);
+        for (p, n) in cube.positions.iter().zip(&cube.normals) {
+            let out = nrmlz_f32_3(sbtr_f32_3(p.position, [0.5; 3]));
+            assert!(dot_product(n.normal, out) > 1.0 - 1e-6, "{:?}", n.normal);
+        }
+    }
+
+    #[test]
+    fn area_and_angle_weigh_unevenly_split_faces_differently() {
+        // some corners touch one face with two triangles and another with one,
+        // which only the area counts
+        let mut by_angle = unit_cube();
+        let mut by_area = unit_cube();
+        smooth_normals(&mut by_angle, Weighting::Angle, None);
+        smooth_normals(&mut by_area, Weighting::Area, None);
+
+        let apart = by_angle
+            .normals
+            .iter()
+            .zip(&by_area.normals)
+            .map(|(a, b)| dot_product(a.normal, b.normal).clamp(-1.0, 1.0).acos())
+            .fold(0.0, f32::max);
+        assert!(apart > 0.1, "{}", apart);
+
+        // outward still, whichever way
+        for (p, n) in by_area.positions.iter().zip(&by_area.normals) {
+            let out = sbtr_f32_3(p.position, [0.5; 3]);
+            assert!(dot_product(n.normal, out) > 0.0);
+        }
+    }
+}
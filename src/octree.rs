@@ -0,0 +1,259 @@
+use crate::f64_3::{dd_f64_3, mltply_f64_3, sbtr_f64_3, vector_length};
+
+// broad phase over a set of points, every node keeps the (sorted) indices of all points below it
+
+pub struct Octree {
+    pub positions: Vec<[f64; 3]>,
+    pub nodes: Vec<Node>,
+}
+
+pub struct Node {
+    pub center: [f64; 3],
+    pub half: f64,
+    pub mean: [f64; 3],
+    pub bodies: Vec<usize>,
+    pub children: Vec<usize>,
+}
+
+pub static LEAF_BODIES: usize = 8;
+static MAX_DEPTH: u32 = 24;
+
+pub fn octree(positions: &[[f64; 3]]) -> Octree {
+    let mut tree = Octree {
+        positions: positions.to_vec(),
+        nodes: vec![],
+    };
+
+    if positions.is_empty() {
+        return tree;
+    }
+
+    let mut min = positions[0];
+    let mut max = positions[0];
+    for p in positions {
+        for d in 0..3 {
+            min[d] = min[d].min(p[d]);
+            max[d] = max[d].max(p[d]);
+        }
+    }
+
+    let center = mltply_f64_3(dd_f64_3(min, max), 0.5);
+    let mut half = 0.0;
+    for d in 0..3 {
+        half = f64::max(half, (max[d] - min[d]) / 2.0);
+    }
+    // keep points on the faces inside and give coincident points a cell with a size
+    half = half * 1.001 + f64::EPSILON;
+
+    subdivide(&mut tree, center, half, (0..positions.len()).collect(), 0);
+
+    tree
+}
+
+fn subdivide(
+    tree: &mut Octree,
+    center: [f64; 3],
+    half: f64,
+    bodies: Vec<usize>,
+    depth: u32,
+) -> usize {
+    let mut mean = [0.0, 0.0, 0.0];
+    for b in &bodies {
+        mean = dd_f64_3(mean, tree.positions[*b]);
+    }
+    mean = mltply_f64_3(mean, 1.0 / bodies.len() as f64);
+
+    let k = tree.nodes.len();
+    tree.nodes.push(Node {
+        center,
+        half,
+        mean,
+        bodies: bodies.clone(),
+        children: vec![],
+    });
+
+    if bodies.len() <= LEAF_BODIES || depth >= MAX_DEPTH {
+        return k;
+    }
+
+    let mut octants: Vec<Vec<usize>> = vec![vec![]; 8];
+    for b in bodies {
+        let p = tree.positions[b];
+        let o = (p[0] >= center[0]) as usize
+            + 2 * (p[1] >= center[1]) as usize
+            + 4 * (p[2] >= center[2]) as usize;
+        octants[o].push(b);
+    }
+
+    for (o, ob) in octants.into_iter().enumerate() {
+        if ob.is_empty() {
+            continue;
+        }
+        let offset = [
+            if o & 1 == 0 { -half / 2.0 } else { half / 2.0 },
+            if o & 2 == 0 { -half / 2.0 } else { half / 2.0 },
+            if o & 4 == 0 { -half / 2.0 } else { half / 2.0 },
+        ];
+        let c = subdivide(tree, dd_f64_3(center, offset), half / 2.0, ob, depth + 1);
+        tree.nodes[k].children.push(c);
+    }
+
+    k
+}
+
+// all points at most radius away from position
+pub fn within(tree: &Octree, position: [f64; 3], radius: f64) -> Vec<usize> {
+    let mut found = vec![];
+    if tree.nodes.is_empty() {
+        return found;
+    }
+
+    let mut stack = vec![0];
+    while let Some(n) = stack.pop() {
+        let node = &tree.nodes[n];
+
+        // distance from the position to the cell's box
+        let mut outside = [0.0, 0.0, 0.0];
+        for d in 0..3 {
+            outside[d] = ((position[d] - node.center[d]).abs() - node.half).max(0.0);
+        }
+        if vector_length(outside) > radius {
+            continue;
+        }
+
+        if node.children.is_empty() {
+            for b in &node.bodies {
+                if vector_length(sbtr_f64_3(tree.positions[*b], position)) <= radius {
+                    found.push(*b);
+                }
+            }
+        } else {
+            stack.extend(node.children.iter());
+        }
+    }
+
+    found
+}
+
+// splits the tree as seen from one of its points into the other points close enough to be
+// visited one by one and whole cells far enough to be replaced by their multipoles;
+// a cell is far when its size over its distance is below theta and it does not hold the point itself
+pub fn barnes_hut(tree: &Octree, body: usize, theta: f64) -> (Vec<usize>, Vec<usize>) {
+    let mut near = vec![];
+    let mut far = vec![];
+    if tree.nodes.is_empty() {
+        return (near, far);
+    }
+
+    let position = tree.positions[body];
+    let mut stack = vec![0];
+    while let Some(n) = stack.pop() {
+        let node = &tree.nodes[n];
+
+        let distance = vector_length(sbtr_f64_3(node.mean, position));
+        let holds_body = node.bodies.binary_search(&body).is_ok();
+        if !holds_body && distance > 0.0 && 2.0 * node.half / distance < theta {
+            far.push(n);
+        } else if node.children.is_empty() {
+            near.extend(node.bodies.iter().filter(|b| **b != body));
+        } else {
+            stack.extend(node.children.iter());
+        }
+    }
+
+    (near, far)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // clumps of points, with some of them right on top of each other
+    fn points(count: usize, seed: u64) -> Vec<[f64; 3]> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let clumps: Vec<[f64; 3]> = (0..5)
+            .map(|_| [0, 1, 2].map(|_| rng.gen_range(-100.0..100.0)))
+            .collect();
+        let mut points: Vec<[f64; 3]> = (0..count)
+            .map(|_| {
+                let clump = clumps[rng.gen_range(0..clumps.len())];
+                dd_f64_3(clump, [0, 1, 2].map(|_| rng.gen_range(-5.0..5.0)))
+            })
+            .collect();
+        points.extend([points[0]; 3]);
+        points
+    }
+
+    #[test]
+    fn every_point_is_in_one_leaf_inside_its_cells() {
+        let points = points(500, 1);
+        let tree = octree(&points);
+
+        let mut seen = vec![0; points.len()];
+        for node in &tree.nodes {
+            assert!(node.bodies.windows(2).all(|w| w[0] < w[1]));
+            for b in &node.bodies {
+                let p = points[*b];
+                assert!((0..3).all(|d| (p[d] - node.center[d]).abs() <= node.half));
+            }
+            if node.children.is_empty() {
+                for b in &node.bodies {
+                    seen[*b] += 1;
+                }
+            } else {
+                let below: usize = node
+                    .children
+                    .iter()
+                    .map(|c| tree.nodes[*c].bodies.len())
+                    .sum();
+                assert_eq!(below, node.bodies.len());
+            }
+        }
+        assert!(seen.iter().all(|s| *s == 1));
+        assert!(tree.nodes.len() > 1);
+    }
+
+    #[test]
+    fn within_finds_what_looking_at_every_point_finds() {
+        let points = points(500, 2);
+        let tree = octree(&points);
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..50 {
+            let from = points[rng.gen_range(0..points.len())];
+            let radius = rng.gen_range(0.0..60.0);
+            let mut found = within(&tree, from, radius);
+            found.sort_unstable();
+            let all: Vec<usize> = (0..points.len())
+                .filter(|i| vector_length(sbtr_f64_3(points[*i], from)) <= radius)
+                .collect();
+            assert_eq!(found, all);
+        }
+        assert!(within(&octree(&[]), [0.0; 3], 1.0).is_empty());
+    }
+
+    #[test]
+    fn barnes_hut_covers_every_other_point_once() {
+        let points = points(500, 4);
+        let tree = octree(&points);
+        for theta in [0.0, 0.5, 1.0] {
+            let mut grouped = 0;
+            for body in (0..points.len()).step_by(37) {
+                let (near, far) = barnes_hut(&tree, body, theta);
+                let mut covered: Vec<usize> = near.clone();
+                for n in &far {
+                    assert!(!tree.nodes[*n].bodies.contains(&body));
+                    covered.extend(&tree.nodes[*n].bodies);
+                }
+                covered.sort_unstable();
+                let others: Vec<usize> = (0..points.len()).filter(|b| *b != body).collect();
+                assert_eq!(covered, others);
+                grouped += far.len();
+            }
+            // nothing is far enough without an opening angle
+            assert_eq!(grouped == 0, theta == 0.0);
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use crate::mesh_cache::{MeshKey, Placed};
+use crate::{fs, instance_buffers, uniform_data, vs, window_size_dependent_setup, Bv};
+
+use std::{collections::HashMap, error::Error, fs::File, io::BufWriter, sync::Arc};
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, CommandBufferBeginInfo, CommandBufferLevel,
+        CommandBufferUsage, CopyImageToBufferInfo, RecordingCommandBuffer, RenderPassBeginInfo,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, DescriptorSet, WriteDescriptorSet,
+    },
+    device::{
+        physical::PhysicalDeviceType, Device, DeviceCreateInfo, Queue, QueueCreateInfo, QueueFlags,
+    },
+    format::Format,
+    image::{Image, ImageCreateInfo, ImageType, ImageUsage},
+    instance::{Instance, InstanceCreateFlags, InstanceCreateInfo},
+    memory::allocator::{AllocationCreateInfo, MemoryTypeFilter, StandardMemoryAllocator},
+    pipeline::{GraphicsPipeline, Pipeline, PipelineBindPoint},
+    render_pass::Framebuffer,
+    sync::{self, GpuFuture},
+    VulkanLibrary,
+};
+
+// renders into an image of its own instead of a swapchain, so frames can be drawn
+// without a window or a display, e.g. on lavapipe, and read back as rgba bytes
+
+pub struct Offscreen {
+    pub extent: [u32; 2],
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    pipeline: Arc<GraphicsPipeline>,
+    framebuffer: Arc<Framebuffer>,
+    image: Arc<Image>,
+    readback: Subbuffer<[u8]>,
+    meshes: HashMap<(MeshKey, usize), Bv>, // uploaded once, for all the frames
+}
+
+// the window's clear color without the hour of the day in it, so renders can be compared
+static CLEAR_COLOR: [f32; 4] = [0.12, 0.14, 0.17, 1.0];
+
+// None when there is no vulkan or no device that draws
+pub fn offscreen(extent: [u32; 2]) -> Option<Offscreen> {
+    let library = VulkanLibrary::new().ok()?;
+    let instance = Instance::new(
+        library,
+        InstanceCreateInfo {
+            flags: InstanceCreateFlags::ENUMERATE_PORTABILITY,
+            ..Default::default()
+        },
+    )
+    .ok()?;
+
+    // any device that draws will do, software ones included
+    let (physical_device, queue_family_index) = instance
+        .enumerate_physical_devices()
+        .ok()?
+        .filter_map(|p| {
+            p.queue_family_properties()
+                .iter()
+                .position(|q| q.queue_flags.intersects(QueueFlags::GRAPHICS))
+                .map(|i| (p, i as u32))
+        })
+        .min_by_key(|(p, _)| match p.properties().device_type {
+            PhysicalDeviceType::DiscreteGpu => 0,
+            PhysicalDeviceType::IntegratedGpu => 1,
+            PhysicalDeviceType::VirtualGpu => 2,
+            PhysicalDeviceType::Cpu => 3,
+            PhysicalDeviceType::Other => 4,
+            _ => 5,
+        })?;
+
+    println!(
+        "Rendering offscreen on: {} (type: {:?})",
+        physical_device.properties().device_name,
+        physical_device.properties().device_type,
+    );
+
+    let (device, mut queues) = Device::new(
+        physical_device,
+        DeviceCreateInfo {
+            queue_create_infos: vec![QueueCreateInfo {
+                queue_family_index,
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let queue = queues.next().unwrap();
+
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(device.clone()));
+    let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
+        device.clone(),
+        Default::default(),
+    ));
+    let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(
+        device.clone(),
+        Default::default(),
+    ));
+
+    let image = Image::new(
+        memory_allocator.clone(),
+        ImageCreateInfo {
+            image_type: ImageType::Dim2d,
+            format: Format::R8G8B8A8_SRGB,
+            extent: [extent[0], extent[1], 1],
+            usage: ImageUsage::COLOR_ATTACHMENT | ImageUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo::default(),
+    )
+    .unwrap();
+
+    let readback = Buffer::new_slice::<u8>(
+        memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_HOST
+                | MemoryTypeFilter::HOST_RANDOM_ACCESS,
+            ..Default::default()
+        },
+        (extent[0] * extent[1] * 4) as u64,
+    )
+    .unwrap();
+
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            color: {
+                format: image.format(),
+                samples: 1,
+                load_op: Clear,
+                store_op: Store,
+            },
+            depth_stencil: {
+                format: Format::D16_UNORM,
+                samples: 1,
+                load_op: Clear,
+                store_op: DontCare,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {depth_stencil},
+        },
+    )
+    .unwrap();
+
+    let vs = vs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+    let fs = fs::load(device.clone())
+        .unwrap()
+        .entry_point("main")
+        .unwrap();
+
+    let (pipeline, mut framebuffers) = window_size_dependent_setup(
+        memory_allocator.clone(),
+        vs,
+        fs,
+        &[image.clone()],
+        render_pass,
+    );
+
+    Some(Offscreen {
+        extent,
+        device,
+        queue,
+        memory_allocator,
+        descriptor_set_allocator,
+        command_buffer_allocator,
+        pipeline,
+        framebuffer: framebuffers.remove(0),
+        image,
+        readback,
+        meshes: HashMap::new(),
+    })
+}
+
+// draws the placed meshes as seen from view_point and returns the picture, row by row in rgba
+pub fn render(
+    offscreen: &mut Offscreen,
+    placed: &[Placed],
+    view_point: [f32; 3],
+    center: [f32; 3],
+    up_direction: [f32; 3],
+) -> Vec<u8> {
+    let data = uniform_data(offscreen.extent, 0.0, view_point, center, up_direction);
+    let bvs = instance_buffers(
+        placed,
+        &mut offscreen.meshes,
+        offscreen.memory_allocator.clone(),
+        &data,
+        offscreen.extent,
+    );
+
+    let uniform_buffer = Buffer::from_data(
+        offscreen.memory_allocator.clone(),
+        BufferCreateInfo {
+            usage: BufferUsage::UNIFORM_BUFFER,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            memory_type_filter: MemoryTypeFilter::PREFER_DEVICE
+                | MemoryTypeFilter::HOST_SEQUENTIAL_WRITE,
+            ..Default::default()
+        },
+        data,
+    )
+    .unwrap();
+
+    let layout = offscreen.pipeline.layout().set_layouts().get(0).unwrap();
+    let set = DescriptorSet::new(
+        offscreen.descriptor_set_allocator.clone(),
+        layout.clone(),
+        [WriteDescriptorSet::buffer(0, uniform_buffer)],
+        [],
+    )
+    .unwrap();
+
+    let mut builder = RecordingCommandBuffer::new(
+        offscreen.command_buffer_allocator.clone(),
+        offscreen.queue.queue_family_index(),
+        CommandBufferLevel::Primary,
+        CommandBufferBeginInfo {
+            usage: CommandBufferUsage::OneTimeSubmit,
+            ..Default::default()
+        },
+    )
+    .unwrap();
+
+    builder
+        .begin_render_pass(
+            RenderPassBeginInfo {
+                clear_values: vec![Some(CLEAR_COLOR.into()), Some(1f32.into())],
+                ..RenderPassBeginInfo::framebuffer(offscreen.framebuffer.clone())
+            },
+            Default::default(),
+        )
+        .unwrap()
+        .bind_pipeline_graphics(offscreen.pipeline.clone())
+        .unwrap()
+        .bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            offscreen.pipeline.layout().clone(),
+            0,
+            set,
+        )
+        .unwrap();
+
+    for (x, placements) in bvs {
+        unsafe {
+            builder
+                .bind_vertex_buffers(0, (x.v, x.n, placements.clone()))
+                .unwrap()
+                .bind_index_buffer(x.i.clone())
+                .unwrap()
+                .draw_indexed(x.i.len() as u32, placements.len() as u32, 0, 0, 0)
+                .unwrap();
+        }
+    }
+
+    builder.end_render_pass(Default::default()).unwrap();
+    builder
+        .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+            offscreen.image.clone(),
+            offscreen.readback.clone(),
+        ))
+        .unwrap();
+
+    let command_buffer = builder.end().unwrap();
+
+    sync::now(offscreen.device.clone())
+        .then_execute(offscreen.queue.clone(), command_buffer)
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    offscreen.readback.read().unwrap().to_vec()
+}
+
+pub fn save_png(path: &str, extent: [u32; 2], pixels: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), extent[0], extent[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh_cache::{generated, translation};
+
+    use std::io::BufReader;
+    use std::path::Path;
+
+    // what the scene below looked like, written anew by a run with GOLDEN_IMAGE=write or when
+    // there is none yet, to be looked at and checked in
+    static GOLDEN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden/offscreen.png");
+    // devices round and rasterize edges a little differently, so channels may be off by this
+    // much and this share of the pixels by more
+    static CHANNEL_TOLERANCE: u8 = 8;
+    static PIXEL_TOLERANCE: f64 = 0.005;
+
+    // stones of a few sizes in a row, their shapes fixed by the default mesh seed
+    fn scene() -> Vec<Placed> {
+        (0..5)
+            .map(|i| Placed {
+                mesh: generated(2.0_f64.powi(i), i as u64),
+                transform: translation([-40.0 + 20.0 * i as f32, 0.0, 10.0 * (i % 2) as f32]),
+            })
+            .collect()
+    }
+
+    fn load_png(path: &str) -> Result<([u32; 2], Vec<u8>), Box<dyn Error>> {
+        let decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        let mut reader = decoder.read_info()?;
+        let extent = [reader.info().width, reader.info().height];
+        let mut pixels = vec![0; 4 * extent[0] as usize * extent[1] as usize];
+        reader.next_frame(&mut pixels)?;
+        Ok((extent, pixels))
+    }
+
+    #[test]
+    fn a_fixed_scene_renders_as_it_did() {
+        let Some(mut offscreen) = offscreen([320, 180]) else {
+            eprintln!("no vulkan device, the golden image is not compared");
+            return;
+        };
+        let pixels = render(
+            &mut offscreen,
+            &scene(),
+            [0.0, -1.0, 1.0],
+            [0.0, 0.0, 0.0],
+            [0.0, -1.0, 0.0],
+        );
+
+        let write = std::env::var("GOLDEN_IMAGE").is_ok_and(|v| v == "write");
+        if write || !Path::new(GOLDEN).exists() {
+            std::fs::create_dir_all(Path::new(GOLDEN).parent().unwrap()).unwrap();
+            save_png(GOLDEN, offscreen.extent, &pixels).unwrap();
+            eprintln!("wrote {}", GOLDEN);
+            return;
+        }
+
+        let (extent, golden) = load_png(GOLDEN).unwrap();
+        assert_eq!(extent, offscreen.extent);
+        let off = pixels
+            .chunks_exact(4)
+            .zip(golden.chunks_exact(4))
+            .filter(|(a, b)| {
+                a.iter()
+                    .zip(*b)
+                    .any(|(x, y)| x.abs_diff(*y) > CHANNEL_TOLERANCE)
+            })
+            .count();
+        let allowed = PIXEL_TOLERANCE * (extent[0] * extent[1]) as f64;
+        assert!(
+            off as f64 <= allowed,
+            "{} pixels differ from {}",
+            off,
+            GOLDEN
+        );
+    }
+}
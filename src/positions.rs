@@ -1,7 +1,7 @@
-use crate::f32_3::{
-    angle_360_of, dd_f32_3, gen_f32_3_unit_on_point_normal_plane, mltply_f32_3, sbtr_f32_3,
-};
-use rand::rngs::ThreadRng;
+use crate::f32_3::{angle_360_of, cross_f32_3, dd_f32_3, mltply_f32_3, nrmlz_f32_3, sbtr_f32_3};
+use crate::shapes::CrossSectionProfile;
+use rand::Rng;
+use std::f32::consts::PI;
 
 use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex};
 #[derive(BufferContents, Vertex, Debug, Clone, Copy)]
@@ -18,22 +18,42 @@ pub struct Normal {
     pub normal: [f32; 3],
 }
 
-pub fn create_points_on_cross_section(
-    c: fn(f32, Vec<f32>) -> f32,
-    c_arg: Vec<f32>,
+// per instance, where a shared mesh is drawn
+#[derive(BufferContents, Vertex, Debug, Clone, Copy)]
+#[repr(C)]
+pub struct Placement {
+    #[format(R32G32B32A32_SFLOAT)]
+    pub transform: [[f32; 4]; 4],
+}
+
+pub fn create_points_on_cross_section<P: CrossSectionProfile + ?Sized, R: Rng + ?Sized>(
+    profile: &P,
     reference_orthogonal: [f32; 3],
     planes_normal: [f32; 3],
     plane_point: [f32; 3],
     points_number: u32,
-    rng: &mut ThreadRng,
+    rng: &mut R,
 ) -> Vec<Position> {
+    // every point somewhere in its own slice of the circle, wholly random angles leave gaps
+    // that the rings get stitched across with folded triangles
+    let across = nrmlz_f32_3(reference_orthogonal);
+    let along = cross_f32_3(planes_normal, across);
+    let slice = 2.0 * PI / points_number as f32;
+
     let mut positions = vec![];
-    for _i in 1..=points_number {
-        let point = gen_f32_3_unit_on_point_normal_plane(planes_normal, plane_point, 1.0, rng);
+    for i in 0..points_number {
+        let around = slice * (i as f32 + rng.gen_range(0.0..1.0));
+        let point = dd_f32_3(
+            plane_point,
+            dd_f32_3(
+                mltply_f32_3(across, around.cos()),
+                mltply_f32_3(along, around.sin()),
+            ),
+        );
 
         let angle = angle_360_of(plane_point, point, reference_orthogonal, planes_normal);
 
-        let distance_multiplier = c(angle, c_arg.clone());
+        let distance_multiplier = profile.distance(angle);
 
         let normal = sbtr_f32_3(point, plane_point);
 
@@ -0,0 +1,189 @@
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// keys of the properties a component can carry, the built in ones and those registered at runtime
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PropertyKey {
+    Spin,
+    Charge,
+    Mass,
+    Color,
+    Inertia0,
+    Inertia1,
+    Inertia2,
+    Custom(u32),
+}
+
+pub const SP: PropertyKey = PropertyKey::Spin;
+pub const EC: PropertyKey = PropertyKey::Charge;
+pub const MS: PropertyKey = PropertyKey::Mass;
+pub const CR: PropertyKey = PropertyKey::Color;
+pub const IN0: PropertyKey = PropertyKey::Inertia0;
+pub const IN1: PropertyKey = PropertyKey::Inertia1;
+pub const IN2: PropertyKey = PropertyKey::Inertia2;
+
+// how two components carrying the same property act on each other when a force has it in its domain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Law {
+    Inert,       // no force
+    LikeRepels,  // coupling times the product of both values, equal signs push apart
+    Attracts,    // coupling times the product of both values, always pulling together
+    ColorCharge, // equal values push apart, different values bind, by the coupling alone
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Registered {
+    pub name: String,
+    pub default: Option<f64>,
+    pub law: Law,
+}
+
+static REGISTRY: Mutex<Vec<Registered>> = Mutex::new(Vec::new());
+// counts registrations, so readers know when their copy of the registry is stale
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // every thread reads its own copy, the force loops look up laws and defaults for every pair
+    // and should not all queue for the lock
+    static SNAPSHOT: RefCell<(u64, Vec<Registered>)> =
+        const { RefCell::new((u64::MAX, Vec::new())) };
+}
+
+fn with_registry<T>(f: impl FnOnce(&[Registered]) -> T) -> T {
+    let generation = GENERATION.load(Ordering::Acquire);
+    SNAPSHOT.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.0 != generation {
+            *s = (generation, REGISTRY.lock().unwrap().clone());
+        }
+        f(&s.1)
+    })
+}
+
+static BUILT_IN: [(PropertyKey, &str, Option<f64>, Law); 7] = [
+    (SP, "SP", Some(0.0), Law::LikeRepels),
+    (EC, "EC", Some(0.0), Law::LikeRepels),
+    (MS, "MS", Some(0.0), Law::Attracts),
+    (CR, "CR", None, Law::ColorCharge), // without a color there is nothing to bind
+    (IN0, "IN0", Some(0.0), Law::Inert),
+    (IN1, "IN1", Some(0.0), Law::Inert),
+    (IN2, "IN2", Some(0.0), Law::Inert),
+];
+
+// a new property, or the one already registered under that name
+pub fn register_property(name: &str, default: Option<f64>, law: Law) -> PropertyKey {
+    if let Some(key) = property_by_name(name) {
+        return key;
+    }
+
+    let mut registry = REGISTRY.lock().unwrap();
+    // another thread may have registered it since
+    if let Some(k) = registry.iter().position(|r| r.name == name) {
+        return PropertyKey::Custom(k as u32);
+    }
+    registry.push(Registered {
+        name: name.to_string(),
+        default,
+        law,
+    });
+    GENERATION.fetch_add(1, Ordering::Release);
+
+    PropertyKey::Custom((registry.len() - 1) as u32)
+}
+
+pub fn property_by_name(name: &str) -> Option<PropertyKey> {
+    if let Some(b) = BUILT_IN.iter().find(|b| b.1 == name) {
+        return Some(b.0);
+    }
+
+    with_registry(|registry| {
+        registry
+            .iter()
+            .position(|r| r.name == name)
+            .map(|k| PropertyKey::Custom(k as u32))
+    })
+}
+
+// keys nobody registered read as inert properties without a default, named by their number
+fn registered<T>(k: u32, f: impl FnOnce(&Registered) -> T) -> Option<T> {
+    with_registry(|r| r.get(k as usize).map(f))
+}
+
+pub fn property_name(key: PropertyKey) -> String {
+    match key {
+        PropertyKey::Custom(k) => {
+            registered(k, |r| r.name.clone()).unwrap_or_else(|| format!("custom {}", k))
+        }
+        _ => built_in(key).1.to_string(),
+    }
+}
+
+// the value read from components that do not carry the property themselves
+pub fn default_value(key: PropertyKey) -> Option<f64> {
+    match key {
+        PropertyKey::Custom(k) => registered(k, |r| r.default).flatten(),
+        _ => built_in(key).2,
+    }
+}
+
+pub fn law_of(key: PropertyKey) -> Law {
+    match key {
+        PropertyKey::Custom(k) => registered(k, |r| r.law).unwrap_or(Law::Inert),
+        _ => built_in(key).3,
+    }
+}
+
+fn built_in(key: PropertyKey) -> &'static (PropertyKey, &'static str, Option<f64>, Law) {
+    BUILT_IN.iter().find(|b| b.0 == key).unwrap()
+}
+
+// every property registered at runtime, in the order of their keys
+pub fn registered_properties() -> Vec<Registered> {
+    REGISTRY.lock().unwrap().clone()
+}
+
+// keys are written as their names, the numbers of custom keys depend on the order of registration
+impl Serialize for PropertyKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&property_name(*self))
+    }
+}
+
+// only names registered before reading, so a misspelt name is an error and not a new property
+impl<'de> Deserialize<'de> for PropertyKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        property_by_name(&name)
+            .ok_or_else(|| D::Error::custom(format!("unknown property {}", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_unregistered_key_is_inert_and_has_no_default() {
+        let key = PropertyKey::Custom(u32::MAX);
+
+        assert_eq!(property_name(key), format!("custom {}", u32::MAX));
+        assert_eq!(default_value(key), None);
+        assert_eq!(law_of(key), Law::Inert);
+        assert_eq!(property_by_name(&property_name(key)), None);
+    }
+
+    #[test]
+    fn registered_keys_read_back_what_was_registered() {
+        let key = register_property("property test", Some(2.0), Law::Attracts);
+
+        assert_eq!(register_property("property test", None, Law::Inert), key);
+        assert_eq!(property_by_name("property test"), Some(key));
+        assert_eq!(property_name(key), "property test");
+        assert_eq!(default_value(key), Some(2.0));
+        assert_eq!(law_of(key), Law::Attracts);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::f64_3::{cross_f64_3, dd_f64_3, dot_f64_3, mltply_f64_3, sbtr_f64_3};
+use crate::hull::stone_hull;
+use crate::magma_ocean::Stone;
+use crate::measure::mass_properties;
+use crate::mesh_cache::{mesh, MeshKey, Placed, Transform};
+
+// stones as solid bodies that turn as well as move, pushed by forces wherever they act on them
+
+// a quaternion is [w, x, y, z], a 3x3 matrix row by row
+
+pub struct RigidBody {
+    pub mesh: MeshKey,
+    pub position: [f64; 3],    // of the centroid
+    pub orientation: [f64; 4], // unit quaternion turning the mesh into the world
+    pub momentum: [f64; 3],
+    pub angular_momentum: [f64; 3], // about the centroid, in the world
+    pub mass: f64,
+    pub inertia: [[f64; 3]; 3], // about the centroid, in the mesh's own frame
+    pub inverse_inertia: [[f64; 3]; 3],
+    pub center: [f64; 3],    // the centroid in the mesh's own coordinates
+    pub hull: Vec<[f64; 3]>, // the corners of the mesh's convex hull, in its own coordinates
+    force: [f64; 3],         // gathered until the next step
+    torque: [f64; 3],
+}
+
+// the mesh as a body of even density, its centroid at position
+pub fn rigid_body(mesh_key: MeshKey, position: [f64; 3], density: f64) -> RigidBody {
+    let made = mesh(&mesh_key);
+    stone_body(mesh_key, &made.levels[0], position, density)
+}
+
+// any stone as a body of even density, drawn as the mesh of the key
+pub fn stone_body(mesh_key: MeshKey, stone: &Stone, position: [f64; 3], density: f64) -> RigidBody {
+    let properties = mass_properties(stone);
+    // a stone wound inside out measures everything negative, mass and inertia alike
+    let sign = if properties.volume < 0.0 { -1.0 } else { 1.0 };
+    let volume = sign * properties.volume;
+    let inertia = properties
+        .inertia
+        .map(|row| row.map(|x| sign * x * density));
+    // a flat stone has no hull, all of it stands in
+    let mut hull = stone_hull(stone);
+    if hull.positions.is_empty() {
+        hull = stone.clone();
+    }
+
+    RigidBody {
+        mesh: mesh_key,
+        position,
+        orientation: [1.0, 0.0, 0.0, 0.0],
+        momentum: [0.0; 3],
+        angular_momentum: [0.0; 3],
+        mass: volume * density,
+        inertia,
+        inverse_inertia: invert(inertia),
+        center: properties.centroid,
+        hull: hull
+            .positions
+            .iter()
+            .map(|p| p.position.map(|x| x as f64))
+            .collect(),
+        force: [0.0; 3],
+        torque: [0.0; 3],
+    }
+}
+
+pub fn quaternion_multiply(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+    [
+        a[0] * b[0] - a[1] * b[1] - a[2] * b[2] - a[3] * b[3],
+        a[0] * b[1] + a[1] * b[0] + a[2] * b[3] - a[3] * b[2],
+        a[0] * b[2] - a[1] * b[3] + a[2] * b[0] + a[3] * b[1],
+        a[0] * b[3] + a[1] * b[2] - a[2] * b[1] + a[3] * b[0],
+    ]
+}
+
+pub fn quaternion_normalize(q: [f64; 4]) -> [f64; 4] {
+    let length = q.iter().map(|x| x * x).sum::<f64>().sqrt();
+    if length > 0.0 {
+        q.map(|x| x / length)
+    } else {
+        [1.0, 0.0, 0.0, 0.0]
+    }
+}
+
+pub fn rotation_matrix(q: [f64; 4]) -> [[f64; 3]; 3] {
+    let [w, x, y, z] = q;
+    [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - w * z),
+            2.0 * (x * z + w * y),
+        ],
+        [
+            2.0 * (x * y + w * z),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - w * x),
+        ],
+        [
+            2.0 * (x * z - w * y),
+            2.0 * (y * z + w * x),
+            1.0 - 2.0 * (x * x + y * y),
+        ],
+    ]
+}
+
+pub fn apply_matrix(m: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+pub fn transpose(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    [0, 1, 2].map(|r| [0, 1, 2].map(|c| m[c][r]))
+}
+
+pub fn matrix_multiply(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    [0, 1, 2].map(|i| [0, 1, 2].map(|j| (0..3).map(|k| a[i][k] * b[k][j]).sum()))
+}
+
+// the zero matrix for a singular one, a flat body does not turn from torques it has no inertia against
+pub fn invert(m: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let [a, b, c] = m;
+    let det = dot_f64_3(a, cross_f64_3(b, c));
+    if det.abs() < f64::EPSILON {
+        return [[0.0; 3]; 3];
+    }
+    let columns = [cross_f64_3(b, c), cross_f64_3(c, a), cross_f64_3(a, b)];
+    transpose(&columns).map(|row| row.map(|x| x / det))
+}
+
+impl RigidBody {
+    // the inverse inertia turned into the world
+    pub fn world_inverse_inertia(&self) -> [[f64; 3]; 3] {
+        let r = rotation_matrix(self.orientation);
+        matrix_multiply(&matrix_multiply(&r, &self.inverse_inertia), &transpose(&r))
+    }
+
+    // none for a body without mass, which is not moved, like one too heavy to be
+    pub fn velocity(&self) -> [f64; 3] {
+        if self.mass > 0.0 {
+            mltply_f64_3(self.momentum, 1.0 / self.mass)
+        } else {
+            [0.0; 3]
+        }
+    }
+
+    pub fn angular_velocity(&self) -> [f64; 3] {
+        apply_matrix(&self.world_inverse_inertia(), self.angular_momentum)
+    }
+
+    // where a point of the mesh is in the world
+    pub fn world_point(&self, local: [f64; 3]) -> [f64; 3] {
+        let r = rotation_matrix(self.orientation);
+        dd_f64_3(
+            self.position,
+            apply_matrix(&r, sbtr_f64_3(local, self.center)),
+        )
+    }
+
+    // how fast a point in the world moves along with the body
+    pub fn point_velocity(&self, point: [f64; 3]) -> [f64; 3] {
+        let arm = sbtr_f64_3(point, self.position);
+        dd_f64_3(self.velocity(), cross_f64_3(self.angular_velocity(), arm))
+    }
+
+    pub fn kinetic_energy(&self) -> f64 {
+        let v = self.velocity();
+        let w = self.angular_velocity();
+        0.5 * self.mass * dot_f64_3(v, v) + 0.5 * dot_f64_3(w, self.angular_momentum)
+    }
+}
+
+// a force acting at a point in the world until the next step, turning the body around its
+// centroid as much as it acts off it
+pub fn apply_force(body: &mut RigidBody, force: [f64; 3], point: [f64; 3]) {
+    body.force = dd_f64_3(body.force, force);
+    let arm = sbtr_f64_3(point, body.position);
+    body.torque = dd_f64_3(body.torque, cross_f64_3(arm, force));
+}
+
+// a push all at once, like a force over a moment too short to step through
+pub fn apply_impulse(body: &mut RigidBody, impulse: [f64; 3], point: [f64; 3]) {
+    body.momentum = dd_f64_3(body.momentum, impulse);
+    let arm = sbtr_f64_3(point, body.position);
+    body.angular_momentum = dd_f64_3(body.angular_momentum, cross_f64_3(arm, impulse));
+}
+
+// semi-implicit euler, the momenta first and then the pose with them; the orientation turns
+// along the angular velocity of the new angular momentum and is normalized again
+pub fn step(body: &mut RigidBody, dt: f64) {
+    body.momentum = dd_f64_3(body.momentum, mltply_f64_3(body.force, dt));
+    body.angular_momentum = dd_f64_3(body.angular_momentum, mltply_f64_3(body.torque, dt));
+    body.force = [0.0; 3];
+    body.torque = [0.0; 3];
+
+    body.position = dd_f64_3(body.position, mltply_f64_3(body.velocity(), dt));
+
+    let w = body.angular_velocity();
+    let spin = quaternion_multiply([0.0, w[0], w[1], w[2]], body.orientation);
+    body.orientation =
+        quaternion_normalize([0, 1, 2, 3].map(|i| body.orientation[i] + 0.5 * dt * spin[i]));
+}
+
+// the mesh turned and moved to where the body is, column by column as the shader takes it
+pub fn world_matrix(body: &RigidBody) -> Transform {
+    let r = rotation_matrix(body.orientation);
+    let t = sbtr_f64_3(body.position, apply_matrix(&r, body.center));
+    [
+        [r[0][0] as f32, r[1][0] as f32, r[2][0] as f32, 0.0],
+        [r[0][1] as f32, r[1][1] as f32, r[2][1] as f32, 0.0],
+        [r[0][2] as f32, r[1][2] as f32, r[2][2] as f32, 0.0],
+        [t[0] as f32, t[1] as f32, t[2] as f32, 1.0],
+    ]
+}
+
+pub fn placed_bodies(bodies: &[RigidBody]) -> Vec<Placed> {
+    bodies
+        .iter()
+        .map(|b| Placed {
+            mesh: b.mesh.clone(),
+            transform: world_matrix(b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::tests::unit_cube;
+    use crate::mesh_cache::generated;
+
+    fn close(a: [f64; 3], b: [f64; 3]) -> bool {
+        (0..3).all(|d| (a[d] - b[d]).abs() < 1e-9 * (1.0 + b[d].abs()))
+    }
+
+    #[test]
+    fn a_force_off_the_centroid_turns_the_body() {
+        let mut body = rigid_body(generated(3.0, 1), [1.0, 2.0, 3.0], 2.0);
+        let (force, arm, dt) = ([0.0, 0.0, 4.0], [0.5, 0.0, 0.0], 0.1);
+        let point = dd_f64_3(body.position, arm);
+        apply_force(&mut body, force, point);
+        assert!(close(body.torque, cross_f64_3(arm, force)));
+
+        step(&mut body, dt);
+        assert!(close(body.momentum, mltply_f64_3(force, dt)));
+        assert!(close(body.angular_momentum, [0.0, -2.0 * dt, 0.0]));
+        assert!(body.orientation != [1.0, 0.0, 0.0, 0.0]);
+
+        // gathered forces last one step only
+        let momentum = body.angular_momentum;
+        step(&mut body, dt);
+        assert!(close(body.angular_momentum, momentum));
+    }
+
+    #[test]
+    fn a_force_through_the_centroid_only_moves_it() {
+        let mut body = rigid_body(generated(3.0, 1), [0.0; 3], 1.0);
+        let centroid = body.position;
+        apply_force(&mut body, [1.0, -2.0, 0.5], centroid);
+        step(&mut body, 0.5);
+        assert!(close(body.angular_momentum, [0.0; 3]));
+        assert!(close(body.position, mltply_f64_3(body.velocity(), 0.5)));
+    }
+
+    #[test]
+    fn an_inside_out_stone_weighs_the_same() {
+        let cube = unit_cube();
+        let mut inside_out = cube.clone();
+        for t in inside_out.indices.chunks_exact_mut(3) {
+            t.swap(1, 2);
+        }
+
+        let key = MeshKey::Asset("cube".to_string());
+        let body = stone_body(key.clone(), &cube, [0.0; 3], 3.0);
+        let turned = stone_body(key, &inside_out, [0.0; 3], 3.0);
+        assert!((body.mass - 3.0).abs() < 1e-9);
+        assert_eq!(body.mass, turned.mass);
+        for d in 0..3 {
+            assert!(turned.inertia[d][d] > 0.0);
+            assert!(close(turned.inertia[d], body.inertia[d]));
+        }
+    }
+
+    #[test]
+    fn a_body_without_mass_stays_put() {
+        let mut body = stone_body(
+            MeshKey::Asset("nothing".to_string()),
+            &Stone {
+                positions: vec![],
+                normals: vec![],
+                indices: vec![],
+            },
+            [1.0, 1.0, 1.0],
+            1.0,
+        );
+        let centroid = body.position;
+        apply_impulse(&mut body, [1.0, 0.0, 0.0], centroid);
+        step(&mut body, 1.0);
+        assert_eq!(body.velocity(), [0.0; 3]);
+        assert_eq!(body.position, [1.0, 1.0, 1.0]);
+        assert_eq!(body.kinetic_energy(), 0.0);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::anomaly::{Anomaly, Component};
+use crate::property::{
+    default_value, law_of, register_property, registered_properties, Registered,
+};
+use crate::stone_io::asset_stone;
+
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+
+// scene files are json holding the anomaly tree and the custom properties it refers to,
+// the properties come first and get registered before the tree is read so their defaults
+// and laws survive the round trip
+
+#[derive(Serialize)]
+struct Scene<'a> {
+    properties: Vec<Registered>,
+    anomaly: &'a Anomaly,
+}
+
+pub fn scene_to_string(anom: &Anomaly) -> Result<String, Box<dyn Error>> {
+    let scene = Scene {
+        properties: registered_properties(),
+        anomaly: anom,
+    };
+    Ok(serde_json::to_string_pretty(&scene)?)
+}
+
+pub fn scene_from_str(text: &str) -> Result<Anomaly, Box<dyn Error>> {
+    let mut scene: serde_json::Value = serde_json::from_str(text)?;
+
+    let properties: Vec<Registered> = match scene.get_mut("properties") {
+        Some(p) => serde_json::from_value(p.take())?,
+        None => vec![],
+    };
+    for p in &properties {
+        let key = register_property(&p.name, p.default, p.law);
+        if default_value(key) != p.default || law_of(key) != p.law {
+            return Err(format!("property {} is already registered differently", p.name).into());
+        }
+    }
+
+    match scene.get_mut("anomaly") {
+        Some(a) => Ok(serde_json::from_value(a.take())?),
+        None => Err("scene without an anomaly".into()),
+    }
+}
+
+pub fn save_scene(path: &str, anom: &Anomaly) -> Result<(), Box<dyn Error>> {
+    fs::write(path, scene_to_string(anom)?)?;
+    Ok(())
+}
+
+// the assets are read right away, a scene naming a file that is missing or broken does not load
+pub fn load_scene(path: &str) -> Result<Anomaly, Box<dyn Error>> {
+    let anom = scene_from_str(&fs::read_to_string(path)?)?;
+
+    let mut assets = vec![];
+    anomaly_assets(&anom, &mut assets);
+    for a in assets {
+        asset_stone(a).map_err(|e| format!("asset {}: {}", a, e))?;
+    }
+
+    Ok(anom)
+}
+
+fn anomaly_assets<'a>(anom: &'a Anomaly, assets: &mut Vec<&'a str>) {
+    for a in &anom.anomaly {
+        anomaly_assets(a, assets);
+    }
+    for c in &anom.component {
+        component_assets(c, assets);
+    }
+}
+
+fn component_assets<'a>(component: &'a Component, assets: &mut Vec<&'a str>) {
+    assets.extend(component.asset.as_deref());
+    for c in &component.component {
+        component_assets(c, assets);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anomaly::{asset, particle, Composition, Force, Property};
+    use crate::distribution::{distribution_by_name, register_distribution};
+    use crate::integrator::Integration;
+    use crate::property::{Law, EC};
+
+    // every point moved one along x
+    fn shifted(points: Vec<[f32; 3]>) -> Vec<[f32; 3]> {
+        points.iter().map(|p| [p[0] + 1.0, p[1], p[2]]).collect()
+    }
+
+    fn scene() -> Anomaly {
+        let flavor = register_property("scene test flavor", Some(0.5), Law::LikeRepels);
+        let shift = register_distribution("scene test shift", shifted);
+
+        Anomaly {
+            anomaly: vec![],
+            component: vec![Component {
+                component: vec![],
+                composition: vec![Composition {
+                    space: vec![[0.0, 1.0, 2.0], [3.0, 4.0, 5.0]],
+                    distribution: vec![distribution_by_name("particular").unwrap(), shift],
+                }],
+                property: vec![
+                    Property {
+                        name: EC,
+                        value: -1.0,
+                    },
+                    Property {
+                        name: flavor,
+                        value: 2.0,
+                    },
+                ],
+                asset: None,
+            }],
+            force: vec![Force {
+                force: vec![],
+                range: vec![4.0],
+                domain: vec![],
+            }],
+            theta: 0.5,
+            integration: Some(Integration::VelocityVerlet),
+        }
+    }
+
+    #[test]
+    fn scenes_survive_a_save_and_a_load() {
+        let path = std::env::temp_dir().join(format!("scene-test-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let written = scene();
+        save_scene(path, &written).unwrap();
+        let read = load_scene(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(
+            scene_to_string(&read).unwrap(),
+            scene_to_string(&written).unwrap()
+        );
+        let composition = &read.component[0].composition[0];
+        assert_eq!(composition.distribution[1].name, "scene test shift");
+        assert_eq!(
+            (composition.distribution[1].distribute)(vec![[0.0; 3]]),
+            vec![[1.0, 0.0, 0.0]]
+        );
+        assert_eq!(read.theta, 0.5);
+        assert_eq!(read.integration, Some(Integration::VelocityVerlet));
+    }
+
+    #[test]
+    fn scenes_from_before_the_opening_angle_load_exactly() {
+        let text = r#"{"anomaly": {"anomaly": [], "component": [], "force": []}}"#;
+        let read = scene_from_str(text).unwrap();
+
+        assert_eq!(read.theta, 0.0);
+        assert_eq!(read.integration, None);
+    }
+
+    #[test]
+    fn scenes_with_a_broken_asset_do_not_load() {
+        let stone = std::env::temp_dir().join(format!("scene-test-{}.obj", std::process::id()));
+        let stone = stone.to_str().unwrap();
+        let path =
+            std::env::temp_dir().join(format!("scene-test-{}-assets.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut scene = particle([0.0; 3], vec![]);
+        scene.anomaly.push(asset([1.0, 0.0, 0.0], stone));
+        save_scene(path, &scene).unwrap();
+
+        // missing
+        assert!(load_scene(path).is_err());
+        // broken
+        fs::write(stone, "v 0 0 0\nf 1 2 3\n").unwrap();
+        assert!(load_scene(path).is_err());
+        // there
+        fs::write(stone, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let read = load_scene(path).unwrap();
+        assert_eq!(read.anomaly[0].component[0].asset.as_deref(), Some(stone));
+
+        fs::remove_file(stone).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+}
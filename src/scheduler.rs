@@ -0,0 +1,147 @@
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+// runs the traversals of the anomaly tree, either on a bounded work stealing pool
+// or one item after another on the calling thread for deterministic runs
+
+pub enum Scheduler {
+    Sequential,
+    Pool(ThreadPool),
+}
+
+pub fn scheduler(workers: usize) -> Scheduler {
+    if workers <= 1 {
+        return Scheduler::Sequential;
+    }
+
+    Scheduler::Pool(
+        ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .thread_name(|i| format!("anomaly-{}", i))
+            .build()
+            .unwrap(),
+    )
+}
+
+pub fn for_each<T, F>(scheduler: &Scheduler, items: &mut [T], f: F)
+where
+    T: Send,
+    F: Fn(&mut T) + Send + Sync,
+{
+    match scheduler {
+        Scheduler::Sequential => items.iter_mut().for_each(f),
+        // nested calls from inside the pool run in place and leave their tasks to be stolen
+        Scheduler::Pool(pool) => pool.install(|| items.par_iter_mut().for_each(f)),
+    }
+}
+
+// results come back in the order of the items
+pub fn map<T, R, F>(scheduler: &Scheduler, items: &mut [T], f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(&mut T) -> R + Send + Sync,
+{
+    match scheduler {
+        Scheduler::Sequential => items.iter_mut().map(f).collect(),
+        Scheduler::Pool(pool) => pool.install(|| items.par_iter_mut().map(f).collect()),
+    }
+}
+
+// f of every index below n, in order
+pub fn map_range<R, F>(scheduler: &Scheduler, n: usize, f: F) -> Vec<R>
+where
+    R: Send,
+    F: Fn(usize) -> R + Send + Sync,
+{
+    match scheduler {
+        Scheduler::Sequential => (0..n).map(f).collect(),
+        Scheduler::Pool(pool) => pool.install(|| (0..n).into_par_iter().map(f).collect()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anomaly::{add_particle_by, anomaly_phase, e, integrate, q, Anomaly};
+    use crate::f32_3::{dd_f32_3, gen_f32_3};
+    use crate::f64_3::gen_f64_3;
+    use crate::integrator::{Integration, PhaseSpace};
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // clusters of electrons and quarks, a few levels deep, far enough apart for theta to
+    // group them
+    fn scene(seed: u64) -> Anomaly {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut root = Anomaly {
+            anomaly: vec![],
+            component: vec![],
+            force: vec![],
+            theta: 0.5,
+            integration: None,
+        };
+        for c in 0..4 {
+            let mut cluster = Anomaly {
+                anomaly: vec![],
+                component: vec![],
+                force: vec![],
+                theta: 0.5,
+                integration: None,
+            };
+            let center = [40.0 * c as f32, 0.0, 0.0];
+            for _ in 0..12 {
+                let position = dd_f32_3(center, gen_f32_3(0.0, 5.0, &mut rng));
+                let inertia = gen_f64_3(0.0, 0.1, &mut rng);
+                let particle = if rng.gen() {
+                    e(position, inertia, rng.gen())
+                } else {
+                    q(
+                        position,
+                        inertia,
+                        rng.gen(),
+                        rng.gen(),
+                        rng.gen_range(0..3),
+                        rng.gen_range(0..1),
+                    )
+                };
+                add_particle_by(&mut cluster, particle);
+            }
+            add_particle_by(&mut root, cluster);
+        }
+        root
+    }
+
+    fn stepped(workers: usize) -> PhaseSpace {
+        let mut anom = scene(11);
+        let scheduler = scheduler(workers);
+        for _ in 0..20 {
+            integrate(&mut anom, &scheduler, Integration::VelocityVerlet, 1e-3);
+        }
+        let mut phase = PhaseSpace {
+            position: vec![],
+            inertia: vec![],
+        };
+        anomaly_phase(&anom, &mut phase);
+        phase
+    }
+
+    #[test]
+    fn the_pool_steps_exactly_like_one_thread() {
+        let sequential = stepped(1);
+        for workers in [2, 4] {
+            let pooled = stepped(workers);
+            assert_eq!(sequential.position, pooled.position);
+            assert_eq!(sequential.inertia, pooled.inertia);
+        }
+
+        // and the steps did move things
+        let mut start = PhaseSpace {
+            position: vec![],
+            inertia: vec![],
+        };
+        anomaly_phase(&scene(11), &mut start);
+        assert_ne!(sequential.inertia, start.inertia);
+    }
+}
@@ -1,5 +1,8 @@
 use crate::f32_3::{dd_f32_3, mltply_f32_3, sbtr_f32_3};
 
+use rand::Rng;
+use std::f32::consts::PI;
+
 pub fn f32_3_dots_collinear(
     point_1: [f32; 3],
     point_2: [f32; 3],
@@ -18,18 +21,326 @@ pub fn f32_3_dots_collinear(
     return planes_points;
 }
 
-pub fn rotational_distance_function_sine(
-    x: f32,
-    c_arg: Vec<f32>, // f_const: f32,
-                     // f_multiplier: f32,
-                     // x_const: f32,
-                     // x_multiplier: f32,
-) -> f32 {
-    return c_arg[0] + c_arg[1] * (c_arg[2] + x * c_arg[3]).sin();
-}
-
 pub fn spherical_progress(points_diff: f32, pln: f32, planes_points: f32) -> f32 {
     let d = (points_diff / 2.0) * (pln - planes_points / 2.0).abs() / (planes_points / 2.0);
-    let f = ((points_diff / 2.0).powi(2) - d.powi(2)).sqrt();
+    let f = ((points_diff / 2.0).powi(2) - d.powi(2)).max(0.0).sqrt(); // rounding at the ends
     return f;
 }
+
+// the outline of a cross section, as the distance from its center at an angle (radians)
+// around it, for a section of about unit size
+pub trait CrossSectionProfile {
+    fn distance(&self, angle: f32) -> f32;
+}
+
+// any function of the angle is a profile, handy to scale or combine others in place
+impl<F: Fn(f32) -> f32> CrossSectionProfile for F {
+    fn distance(&self, angle: f32) -> f32 {
+        self(angle)
+    }
+}
+
+pub struct Circle {
+    pub radius: f32,
+}
+
+impl CrossSectionProfile for Circle {
+    fn distance(&self, _angle: f32) -> f32 {
+        self.radius
+    }
+}
+
+// |x / a|^n + |y / b|^n = 1, an ellipse for an exponent of 2, boxier above and pinched below
+pub struct Superellipse {
+    pub a: f32,
+    pub b: f32,
+    pub exponent: f32,
+}
+
+impl CrossSectionProfile for Superellipse {
+    fn distance(&self, angle: f32) -> f32 {
+        let x = (angle.cos() / self.a).abs().powf(self.exponent);
+        let y = (angle.sin() / self.b).abs().powf(self.exponent);
+        (x + y).powf(-1.0 / self.exponent)
+    }
+}
+
+pub struct Harmonic {
+    pub frequency: u32, // waves around the section
+    pub amplitude: f32,
+    pub phase: f32,
+}
+
+// a radius with sine waves added around it
+pub struct Fourier {
+    pub radius: f32,
+    pub harmonics: Vec<Harmonic>,
+}
+
+impl CrossSectionProfile for Fourier {
+    fn distance(&self, angle: f32) -> f32 {
+        self.harmonics.iter().fold(self.radius, |d, h| {
+            d + h.amplitude * (h.phase + angle * h.frequency as f32).sin()
+        })
+    }
+}
+
+// straight flanks between the outer radius at the points and the inner one between them
+pub struct Star {
+    pub points: u32,
+    pub inner: f32,
+    pub outer: f32,
+}
+
+impl CrossSectionProfile for Star {
+    fn distance(&self, angle: f32) -> f32 {
+        let s = (angle / (2.0 * PI) * self.points as f32).rem_euclid(1.0);
+        self.inner + (self.outer - self.inner) * (2.0 * s - 1.0).abs()
+    }
+}
+
+// a radius wobbling smoothly through random knots spaced evenly around the section
+pub struct NoisyRadius {
+    pub radius: f32,
+    pub amplitude: f32,
+    pub knots: Vec<f32>, // between -1 and 1
+}
+
+pub fn noisy_radius<R: Rng + ?Sized>(
+    radius: f32,
+    amplitude: f32,
+    knots: u32,
+    rng: &mut R,
+) -> NoisyRadius {
+    NoisyRadius {
+        radius,
+        amplitude,
+        knots: (0..knots.max(1))
+            .map(|_| rng.gen_range(-1.0..1.0))
+            .collect(),
+    }
+}
+
+impl CrossSectionProfile for NoisyRadius {
+    fn distance(&self, angle: f32) -> f32 {
+        let n = self.knots.len();
+        let x = (angle / (2.0 * PI)).rem_euclid(1.0) * n as f32;
+        let i = (x as usize).min(n - 1);
+        let s = x - i as f32;
+        let s = s * s * (3.0 - 2.0 * s);
+        let knot = self.knots[i] * (1.0 - s) + self.knots[(i + 1) % n] * s;
+        self.radius * (1.0 + self.amplitude * knot)
+    }
+}
+
+static UNIT_CIRCLE: Circle = Circle { radius: 1.0 };
+
+// the sections along a stone's axis
+pub enum AxisProfile {
+    Constant(Box<dyn CrossSectionProfile>),
+    PerPlane(Vec<Box<dyn CrossSectionProfile>>), // the last one repeats if there are more planes
+    Interpolated(Box<dyn CrossSectionProfile>, Box<dyn CrossSectionProfile>), // first to last plane
+}
+
+// one section in between two others
+pub struct Blend<'a> {
+    pub from: &'a dyn CrossSectionProfile,
+    pub to: &'a dyn CrossSectionProfile,
+    pub f: f32,
+}
+
+impl CrossSectionProfile for Blend<'_> {
+    fn distance(&self, angle: f32) -> f32 {
+        self.from.distance(angle) * (1.0 - self.f) + self.to.distance(angle) * self.f
+    }
+}
+
+impl AxisProfile {
+    // the section of a plane out of planes along the axis
+    pub fn at(&self, plane: u32, planes: u32) -> Blend<'_> {
+        match self {
+            AxisProfile::Constant(p) => Blend {
+                from: p.as_ref(),
+                to: p.as_ref(),
+                f: 0.0,
+            },
+            AxisProfile::PerPlane(ps) => {
+                // without any sections every plane is a circle
+                let p = match ps.get((plane as usize).min(ps.len().max(1) - 1)) {
+                    Some(p) => p.as_ref(),
+                    None => &UNIT_CIRCLE,
+                };
+                Blend {
+                    from: p,
+                    to: p,
+                    f: 0.0,
+                }
+            }
+            AxisProfile::Interpolated(from, to) => Blend {
+                from: from.as_ref(),
+                to: to.as_ref(),
+                f: plane as f32 / (planes.max(2) - 1) as f32,
+            },
+        }
+    }
+}
+
+// one of the shapes above with random parameters, sometimes turning into another along the axis
+pub fn random_profile<R: Rng + ?Sized>(rng: &mut R) -> AxisProfile {
+    let section = |rng: &mut R| -> Box<dyn CrossSectionProfile> {
+        match rng.gen_range(0..5) {
+            0 => Box::new(Circle { radius: 1.0 }),
+            1 => Box::new(Superellipse {
+                a: rng.gen_range(0.6..1.0),
+                b: rng.gen_range(0.6..1.0),
+                exponent: rng.gen_range(1.5..4.0),
+            }),
+            2 => Box::new(Fourier {
+                radius: 1.0,
+                harmonics: (0..rng.gen_range(1..4))
+                    .map(|_| Harmonic {
+                        frequency: rng.gen_range(2..7),
+                        amplitude: rng.gen_range(0.02..0.12),
+                        phase: rng.gen_range(0.0..2.0 * PI),
+                    })
+                    .collect(),
+            }),
+            3 => Box::new(Star {
+                points: rng.gen_range(3..8),
+                inner: rng.gen_range(0.7..0.9),
+                outer: 1.0,
+            }),
+            _ => Box::new(noisy_radius(1.0, rng.gen_range(0.05..0.25), 8, rng)),
+        }
+    };
+
+    match rng.gen_range(0..5) {
+        0 | 1 => AxisProfile::Constant(section(rng)),
+        2 | 3 => {
+            let from = section(rng);
+            AxisProfile::Interpolated(from, section(rng))
+        }
+        // layers, a little rougher or smoother each
+        _ => AxisProfile::PerPlane(
+            (0..rng.gen_range(8..32))
+                .map(|_| {
+                    let amplitude = rng.gen_range(0.02..0.1);
+                    Box::new(noisy_radius(1.0, amplitude, 8, rng)) as Box<dyn CrossSectionProfile>
+                })
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magma_ocean::{magma, petrify};
+    use crate::validation::{validate, Problem};
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn sections(rng: &mut StdRng) -> Vec<(&'static str, Box<dyn CrossSectionProfile>)> {
+        vec![
+            ("circle", Box::new(Circle { radius: 1.0 })),
+            (
+                "superellipse",
+                Box::new(Superellipse {
+                    a: rng.gen_range(0.6..1.0),
+                    b: rng.gen_range(0.6..1.0),
+                    exponent: rng.gen_range(1.5..4.0),
+                }),
+            ),
+            (
+                "star",
+                Box::new(Star {
+                    points: rng.gen_range(3..8),
+                    inner: rng.gen_range(0.7..0.9),
+                    outer: 1.0,
+                }),
+            ),
+            (
+                "fourier",
+                Box::new(Fourier {
+                    radius: 1.0,
+                    harmonics: (0..3)
+                        .map(|_| Harmonic {
+                            frequency: rng.gen_range(2..7),
+                            amplitude: rng.gen_range(0.02..0.12),
+                            phase: rng.gen_range(0.0..2.0 * PI),
+                        })
+                        .collect(),
+                }),
+            ),
+            ("noisy", Box::new(noisy_radius(1.0, 0.25, 8, rng))),
+        ]
+    }
+
+    #[test]
+    fn sections_are_positive_and_go_once_around() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            for (name, section) in sections(&mut rng) {
+                for k in 0..360 {
+                    let angle = (k as f32).to_radians();
+                    let d = section.distance(angle);
+                    assert!(d > 0.0, "{} at {}: {}", name, k, d);
+                    for turns in [-1.0, 1.0, 3.0] {
+                        let again = section.distance(angle + turns * 2.0 * PI);
+                        assert!(
+                            (again - d).abs() < 1e-4,
+                            "{} at {}: {} {}",
+                            name,
+                            k,
+                            d,
+                            again
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_per_plane_profiles_are_circles() {
+        let profile = AxisProfile::PerPlane(vec![]);
+        for plane in [0, 5, 29] {
+            assert_eq!(profile.at(plane, 30).distance(1.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn stones_petrify_closed_with_every_profile() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut profiles: Vec<(&str, AxisProfile)> = sections(&mut rng)
+            .into_iter()
+            .map(|(name, s)| (name, AxisProfile::Constant(s)))
+            .collect();
+        let mut ends = sections(&mut rng).into_iter().map(|(_, s)| s);
+        profiles.push((
+            "interpolated",
+            AxisProfile::Interpolated(ends.next().unwrap(), ends.nth(2).unwrap()),
+        ));
+        profiles.push((
+            "per plane",
+            AxisProfile::PerPlane(sections(&mut rng).into_iter().map(|(_, s)| s).collect()),
+        ));
+        profiles.push(("empty per plane", AxisProfile::PerPlane(vec![])));
+
+        for (name, profile) in &profiles {
+            for _ in 0..10 {
+                let stone = petrify(magma(2, 10.0, &mut rng), profile, &mut rng);
+                let report = validate(&stone);
+                // the welded tips aside
+                let open = report.count(|p| !matches!(p, Problem::DegenerateTriangle { .. }));
+                assert!(
+                    !stone.indices.is_empty() && open == 0,
+                    "{}: {}",
+                    name,
+                    report
+                );
+            }
+        }
+    }
+}
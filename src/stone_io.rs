@@ -0,0 +1,718 @@
+use crate::f32_3::nrmlz_f32_3;
+use crate::magma_ocean::Stone;
+use crate::normals::{smooth_normals, Weighting};
+use crate::positions::{Normal, Position};
+
+use serde_json::json;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+// writes stones out for other tools, several stones go into one file as separate objects
+// (obj, gltf) or merged into one mesh (ply, which knows only one), and reads meshes of
+// other tools in as one stone each
+
+// picks the format by the file's extension
+pub fn write_stones(path: &str, stones: &[Stone]) -> Result<(), Box<dyn Error>> {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("obj") => write_obj(path, stones),
+        Some("ply") => write_ply(path, stones),
+        Some("gltf") => write_gltf(path, stones),
+        _ => Err(format!("no stone format for {}", path).into()),
+    }
+}
+
+// every format pairs a normal with each vertex, a stone with fewer would shift or drop them
+fn check_normals(stones: &[Stone]) -> Result<(), Box<dyn Error>> {
+    match stones
+        .iter()
+        .position(|s| s.normals.len() != s.positions.len())
+    {
+        Some(k) => Err(format!(
+            "stone {} has {} normals for {} vertices",
+            k,
+            stones[k].normals.len(),
+            stones[k].positions.len()
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+pub fn write_obj(path: &str, stones: &[Stone]) -> Result<(), Box<dyn Error>> {
+    check_normals(stones)?;
+    let mut out = BufWriter::new(File::create(path)?);
+
+    // obj counts vertices from 1 and across objects
+    let mut offset = 1;
+    for (k, stone) in stones.iter().enumerate() {
+        writeln!(out, "o stone_{}", k)?;
+        for p in &stone.positions {
+            writeln!(
+                out,
+                "v {} {} {}",
+                p.position[0], p.position[1], p.position[2]
+            )?;
+        }
+        for n in &stone.normals {
+            writeln!(out, "vn {} {} {}", n.normal[0], n.normal[1], n.normal[2])?;
+        }
+        for t in stone.indices.chunks(3) {
+            let (a, b, c) = (t[0] + offset, t[1] + offset, t[2] + offset);
+            writeln!(out, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+        }
+        offset += stone.positions.len() as u32;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+pub fn write_ply(path: &str, stones: &[Stone]) -> Result<(), Box<dyn Error>> {
+    let vertices: usize = stones.iter().map(|s| s.positions.len()).sum();
+    let faces: usize = stones.iter().map(|s| s.indices.len() / 3).sum();
+    check_normals(stones)?;
+
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(
+        out,
+        "ply\nformat binary_little_endian 1.0\n\
+         element vertex {}\n\
+         property float x\nproperty float y\nproperty float z\n\
+         property float nx\nproperty float ny\nproperty float nz\n\
+         element face {}\n\
+         property list uchar uint vertex_indices\n\
+         end_header\n",
+        vertices, faces
+    )?;
+
+    for stone in stones {
+        for (p, n) in stone.positions.iter().zip(stone.normals.iter()) {
+            for x in p.position.iter().chain(n.normal.iter()) {
+                out.write_all(&x.to_le_bytes())?;
+            }
+        }
+    }
+
+    let mut offset = 0;
+    for stone in stones {
+        for t in stone.indices.chunks(3) {
+            out.write_all(&[3])?;
+            for i in t {
+                out.write_all(&(i + offset).to_le_bytes())?;
+            }
+        }
+        offset += stone.positions.len() as u32;
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+// gl enums gltf refers to
+static ARRAY_BUFFER: u32 = 34962;
+static ELEMENT_ARRAY_BUFFER: u32 = 34963;
+static FLOAT: u32 = 5126;
+static UNSIGNED_INT: u32 = 5125;
+
+// a .gltf with the json and a .bin next to it with the buffers, one mesh and node per stone
+pub fn write_gltf(path: &str, stones: &[Stone]) -> Result<(), Box<dyn Error>> {
+    check_normals(stones)?;
+    let bin_path = Path::new(path).with_extension("bin");
+    let bin_name = bin_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or("no file name for the gltf buffer")?
+        .to_string();
+
+    let mut bin: Vec<u8> = vec![];
+    let mut buffer_views = vec![];
+    let mut accessors = vec![];
+    let mut meshes = vec![];
+    let mut nodes = vec![];
+
+    // gltf has no use for empty primitives
+    for stone in stones.iter().filter(|s| !s.indices.is_empty()) {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for p in &stone.positions {
+            for d in 0..3 {
+                min[d] = min[d].min(p.position[d]);
+                max[d] = max[d].max(p.position[d]);
+            }
+        }
+
+        // all four byte values, so every view stays aligned
+        let position_view = push_view(
+            &mut bin,
+            &mut buffer_views,
+            stone
+                .positions
+                .iter()
+                .flat_map(|p| p.position.map(f32::to_le_bytes))
+                .flatten()
+                .collect(),
+            ARRAY_BUFFER,
+        );
+        let normal_view = push_view(
+            &mut bin,
+            &mut buffer_views,
+            stone
+                .normals
+                .iter()
+                .flat_map(|n| n.normal.map(f32::to_le_bytes))
+                .flatten()
+                .collect(),
+            ARRAY_BUFFER,
+        );
+        let index_view = push_view(
+            &mut bin,
+            &mut buffer_views,
+            stone.indices.iter().flat_map(|i| i.to_le_bytes()).collect(),
+            ELEMENT_ARRAY_BUFFER,
+        );
+
+        let a = accessors.len();
+        accessors.push(json!({
+            "bufferView": position_view,
+            "componentType": FLOAT,
+            "count": stone.positions.len(),
+            "type": "VEC3",
+            "min": min,
+            "max": max,
+        }));
+        accessors.push(json!({
+            "bufferView": normal_view,
+            "componentType": FLOAT,
+            "count": stone.normals.len(),
+            "type": "VEC3",
+        }));
+        accessors.push(json!({
+            "bufferView": index_view,
+            "componentType": UNSIGNED_INT,
+            "count": stone.indices.len(),
+            "type": "SCALAR",
+        }));
+
+        nodes.push(json!({ "mesh": meshes.len() }));
+        meshes.push(json!({
+            "primitives": [{
+                "attributes": { "POSITION": a, "NORMAL": a + 1 },
+                "indices": a + 2,
+            }],
+        }));
+    }
+
+    let gltf = json!({
+        "asset": { "version": "2.0", "generator": "erosion-coding" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<usize>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "accessors": accessors,
+        "bufferViews": buffer_views,
+        "buffers": [{ "uri": bin_name, "byteLength": bin.len() }],
+    });
+
+    std::fs::write(&bin_path, &bin)?;
+    std::fs::write(path, serde_json::to_string_pretty(&gltf)?)?;
+    Ok(())
+}
+
+// appends bytes to the buffer and describes them as a view, returning its index
+fn push_view(
+    bin: &mut Vec<u8>,
+    buffer_views: &mut Vec<serde_json::Value>,
+    bytes: Vec<u8>,
+    target: u32,
+) -> usize {
+    buffer_views.push(json!({
+        "buffer": 0,
+        "byteOffset": bin.len(),
+        "byteLength": bytes.len(),
+        "target": target,
+    }));
+    bin.extend(bytes);
+    buffer_views.len() - 1
+}
+
+// picks the format by the file's extension, every object in the file ends up in the one stone
+pub fn read_stone(path: &str) -> Result<Stone, Box<dyn Error>> {
+    match Path::new(path).extension().and_then(|e| e.to_str()) {
+        Some("obj") => read_obj(path),
+        Some("ply") => read_ply(path),
+        _ => Err(format!("no stone format for {}", path).into()),
+    }
+}
+
+static ASSETS: Mutex<Vec<(String, Stone)>> = Mutex::new(Vec::new());
+
+// a stone read from a file once and handed out as copies afterwards, views ask every frame
+pub fn asset_stone(path: &str) -> Result<Stone, Box<dyn Error>> {
+    if let Some(a) = ASSETS.lock().unwrap().iter().find(|a| a.0 == path) {
+        return Ok(a.1.clone());
+    }
+
+    let stone = read_stone(path)?;
+    ASSETS
+        .lock()
+        .unwrap()
+        .push((path.to_string(), stone.clone()));
+    Ok(stone)
+}
+
+pub fn read_obj(path: &str) -> Result<Stone, Box<dyn Error>> {
+    let text = std::fs::read_to_string(path)?;
+
+    let mut vs: Vec<[f32; 3]> = vec![];
+    let mut vns: Vec<[f32; 3]> = vec![];
+
+    // a vertex of the stone for every pair of position and normal the faces use
+    let mut corners: HashMap<(usize, Option<usize>), u32> = HashMap::new();
+    let mut positions = vec![];
+    let mut normals: Vec<Option<[f32; 3]>> = vec![];
+    let mut indices = vec![];
+
+    for (l, line) in text.lines().enumerate() {
+        let mut tokens = line.split_whitespace();
+        let at = |e: Box<dyn Error>| format!("{}:{}: {}", path, l + 1, e);
+        match tokens.next() {
+            Some("v") => vs.push(obj_vector(&mut tokens).map_err(at)?),
+            Some("vn") => vns.push(obj_vector(&mut tokens).map_err(at)?),
+            Some("f") => {
+                let mut face = vec![];
+                for token in tokens {
+                    let mut parts = token.split('/');
+                    let v = obj_index(parts.next(), vs.len()).map_err(at)?;
+                    let vn = match parts.nth(1) {
+                        Some(n) if !n.is_empty() => {
+                            Some(obj_index(Some(n), vns.len()).map_err(at)?)
+                        }
+                        _ => None,
+                    };
+
+                    let corner = *corners.entry((v, vn)).or_insert_with(|| {
+                        positions.push(Position { position: vs[v] });
+                        normals.push(vn.map(|n| vns[n]));
+                        (positions.len() - 1) as u32
+                    });
+                    face.push(corner);
+                }
+                if face.len() < 3 {
+                    return Err(at("face with less than three corners".into()).into());
+                }
+
+                // quads and larger polygons as fans around their first corner
+                for k in 1..face.len() - 1 {
+                    indices.extend([face[0], face[k], face[k + 1]]);
+                }
+            }
+            _ => {} // texture coordinates, groups, materials and comments are of no use to a stone
+        }
+    }
+
+    Ok(stone_of(positions, normals, indices))
+}
+
+fn obj_vector<'a>(tokens: &mut impl Iterator<Item = &'a str>) -> Result<[f32; 3], Box<dyn Error>> {
+    let mut v = [0.0; 3];
+    for x in v.iter_mut() {
+        *x = tokens
+            .next()
+            .ok_or("vector with less than three values")?
+            .parse()?;
+    }
+    Ok(v)
+}
+
+// obj counts from 1, and backwards from the last one read with negative numbers
+fn obj_index(token: Option<&str>, count: usize) -> Result<usize, Box<dyn Error>> {
+    let i: i64 = token.ok_or("face corner without an index")?.parse()?;
+    let index = if i < 0 { count as i64 + i } else { i - 1 };
+    if index < 0 || index >= count as i64 {
+        return Err(format!("index {} out of {} read so far", i, count).into());
+    }
+    Ok(index as usize)
+}
+
+pub fn read_ply(path: &str) -> Result<Stone, Box<dyn Error>> {
+    let data = std::fs::read(path)?;
+
+    // the header's lines may end in \r\n, the body starts right after the one ending it
+    let end = b"end_header";
+    let end_at = data
+        .windows(end.len())
+        .position(|w| w == end)
+        .ok_or("ply without end_header")?
+        + end.len();
+    let header_length = match &data[end_at..] {
+        [b'\r', b'\n', ..] => end_at + 2,
+        [b'\n', ..] => end_at + 1,
+        _ => return Err("end_header without a line end".into()),
+    };
+    let header = std::str::from_utf8(&data[..header_length])?.replace('\r', "");
+
+    let mut lines = header.lines();
+    if lines.next() != Some("ply") {
+        return Err("not a ply file".into());
+    }
+
+    // elements with their count and properties, a property with a count type is a list
+    let mut format = "";
+    let mut elements: Vec<(&str, usize, Vec<PlyProperty>)> = vec![];
+    for line in lines {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["format", f, _] => format = f,
+            ["element", name, count] => elements.push((name, count.parse()?, vec![])),
+            ["property", "list", count, kind, name] => elements
+                .last_mut()
+                .ok_or("property outside of an element")?
+                .2
+                .push((name, kind, Some(count))),
+            ["property", kind, name] => elements
+                .last_mut()
+                .ok_or("property outside of an element")?
+                .2
+                .push((name, kind, None)),
+            _ => {} // comments, obj_info and end_header
+        }
+    }
+
+    let mut body = match format {
+        "ascii" => PlyBody::Ascii(
+            std::str::from_utf8(&data[header_length..])?
+                .split_whitespace()
+                .collect::<Vec<&str>>()
+                .into_iter(),
+        ),
+        "binary_little_endian" => PlyBody::Binary(&data[header_length..], false),
+        "binary_big_endian" => PlyBody::Binary(&data[header_length..], true),
+        _ => return Err(format!("unknown ply format {}", format).into()),
+    };
+
+    let mut positions = vec![];
+    let mut normals: Vec<Option<[f32; 3]>> = vec![];
+    let mut indices = vec![];
+
+    for (element, count, properties) in &elements {
+        for _ in 0..*count {
+            let mut position = [0.0; 3];
+            let mut normal = [0.0; 3];
+            let mut has_normal = false;
+            let mut face = vec![];
+
+            for (name, kind, list) in properties {
+                if let Some(count_kind) = list {
+                    let n = body.scalar(count_kind)? as usize;
+                    for _ in 0..n {
+                        face.push(body.scalar(kind)? as u32);
+                    }
+                    continue;
+                }
+
+                let x = body.scalar(kind)? as f32;
+                match *name {
+                    "x" => position[0] = x,
+                    "y" => position[1] = x,
+                    "z" => position[2] = x,
+                    "nx" | "ny" | "nz" => {
+                        has_normal = true;
+                        normal[["nx", "ny", "nz"].iter().position(|n| n == name).unwrap()] = x;
+                    }
+                    _ => {}
+                }
+            }
+
+            match *element {
+                "vertex" => {
+                    positions.push(Position { position });
+                    normals.push(if has_normal { Some(normal) } else { None });
+                }
+                "face" => {
+                    if face.len() < 3 {
+                        return Err("face with less than three corners".into());
+                    }
+                    for k in 1..face.len() - 1 {
+                        indices.extend([face[0], face[k], face[k + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if let Some(i) = indices.iter().find(|i| **i as usize >= positions.len()) {
+        return Err(format!("index {} out of {} vertices", i, positions.len()).into());
+    }
+
+    Ok(stone_of(positions, normals, indices))
+}
+
+// name, type and, for lists, the type of their count
+type PlyProperty<'a> = (&'a str, &'a str, Option<&'a str>);
+
+enum PlyBody<'a> {
+    Ascii(std::vec::IntoIter<&'a str>),
+    Binary(&'a [u8], bool), // the bytes left and whether they are big endian
+}
+
+impl PlyBody<'_> {
+    // the next value of the given ply type, widened to f64
+    fn scalar(&mut self, kind: &str) -> Result<f64, Box<dyn Error>> {
+        match self {
+            PlyBody::Ascii(tokens) => Ok(tokens.next().ok_or("ply ends early")?.parse()?),
+            PlyBody::Binary(bytes, big_endian) => {
+                let size = match kind {
+                    "char" | "int8" | "uchar" | "uint8" => 1,
+                    "short" | "int16" | "ushort" | "uint16" => 2,
+                    "int" | "int32" | "uint" | "uint32" | "float" | "float32" => 4,
+                    "double" | "float64" => 8,
+                    _ => return Err(format!("unknown ply type {}", kind).into()),
+                };
+                if bytes.len() < size {
+                    return Err("ply ends early".into());
+                }
+
+                let mut b = [0u8; 8];
+                b[..size].copy_from_slice(&bytes[..size]);
+                if *big_endian {
+                    b[..size].reverse();
+                }
+                *bytes = &bytes[size..];
+
+                Ok(match kind {
+                    "char" | "int8" => b[0] as i8 as f64,
+                    "uchar" | "uint8" => b[0] as f64,
+                    "short" | "int16" => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    "ushort" | "uint16" => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    "int" | "int32" => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    "uint" | "uint32" => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    "float" | "float32" => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    _ => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+}
+
+// keeps the normals a file came with if it has one for every vertex, otherwise they are
+// smoothed from the faces, which are taken to be counterclockwise seen from outside
+fn stone_of(positions: Vec<Position>, normals: Vec<Option<[f32; 3]>>, indices: Vec<u32>) -> Stone {
+    let mut stone = Stone {
+        positions,
+        normals: vec![],
+        indices,
+    };
+
+    if normals.iter().all(|n| n.is_some()) {
+        stone.normals = normals
+            .into_iter()
+            .map(|n| Normal {
+                normal: nrmlz_f32_3(n.unwrap()),
+            })
+            .collect();
+    } else {
+        smooth_normals(&mut stone, Weighting::Area, None);
+    }
+
+    stone
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magma_ocean::{magma, petrify};
+    use crate::shapes::random_profile;
+
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn petrified(seed: u64) -> Stone {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let profile = random_profile(&mut rng);
+        petrify(magma(2, 10.0, &mut rng), &profile, &mut rng)
+    }
+
+    // a file in the temporary directory, unique to this test run
+    fn temporary(name: &str) -> String {
+        let file = format!("stone-io-{}-{}", std::process::id(), name);
+        std::env::temp_dir()
+            .join(file)
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn assert_same(read: &Stone, written: &Stone) {
+        assert_eq!(read.indices, written.indices);
+        assert_eq!(read.positions.len(), written.positions.len());
+        for (r, w) in read.positions.iter().zip(&written.positions) {
+            assert_eq!(r.position, w.position);
+        }
+        for (r, w) in read.normals.iter().zip(&written.normals) {
+            assert!((0..3).all(|d| (r.normal[d] - w.normal[d]).abs() < 1e-5));
+        }
+    }
+
+    #[test]
+    fn petrified_stones_come_back_from_obj() {
+        let stone = petrified(1);
+        let path = temporary("round.obj");
+        write_stones(&path, std::slice::from_ref(&stone)).unwrap();
+        let read = read_stone(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_same(&read, &stone);
+    }
+
+    #[test]
+    fn petrified_stones_come_back_from_ply() {
+        let stone = petrified(2);
+        let path = temporary("round.ply");
+        write_stones(&path, std::slice::from_ref(&stone)).unwrap();
+        let read = read_stone(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_same(&read, &stone);
+    }
+
+    #[test]
+    fn gltf_accessors_describe_their_buffers() {
+        let stones = [petrified(3), petrified(4)];
+        let path = temporary("accessors.gltf");
+        write_stones(&path, &stones).unwrap();
+        let gltf: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let bin = std::fs::read(Path::new(&path).with_extension("bin")).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(Path::new(&path).with_extension("bin")).unwrap();
+
+        assert_eq!(gltf["buffers"][0]["byteLength"], bin.len());
+        let accessors = gltf["accessors"].as_array().unwrap();
+        let views = gltf["bufferViews"].as_array().unwrap();
+        assert_eq!(accessors.len(), 3 * stones.len());
+
+        for (k, stone) in stones.iter().enumerate() {
+            let counts = [
+                stone.positions.len(),
+                stone.normals.len(),
+                stone.indices.len(),
+            ];
+            // three floats a position or normal, one unsigned int an index
+            let sizes = [12, 12, 4];
+            for a in 0..3 {
+                let accessor = &accessors[3 * k + a];
+                let view = &views[accessor["bufferView"].as_u64().unwrap() as usize];
+                assert_eq!(accessor["count"], counts[a]);
+                assert_eq!(view["byteLength"], counts[a] * sizes[a]);
+                let end =
+                    view["byteOffset"].as_u64().unwrap() + view["byteLength"].as_u64().unwrap();
+                assert!(end as usize <= bin.len());
+            }
+        }
+    }
+
+    #[test]
+    fn stones_missing_normals_are_not_written() {
+        let mut stone = petrified(5);
+        stone.normals.pop();
+        for format in ["obj", "ply", "gltf"] {
+            let path = temporary(&format!("short.{}", format));
+            assert!(write_stones(&path, std::slice::from_ref(&stone)).is_err());
+            assert!(!Path::new(&path).exists());
+        }
+    }
+
+    fn read_text(name: &str, text: &str) -> Stone {
+        let path = temporary(name);
+        std::fs::write(&path, text).unwrap();
+        let read = read_stone(&path);
+        std::fs::remove_file(&path).unwrap();
+        read.unwrap()
+    }
+
+    #[test]
+    fn obj_faces_count_from_one_or_back_from_the_last_vertex() {
+        let forward = read_text(
+            "forward.obj",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1\n",
+        );
+        let backward = read_text(
+            "backward.obj",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nf -3//-1 -2//-1 -1//-1\n",
+        );
+
+        for stone in [forward, backward] {
+            assert_eq!(stone.indices, vec![0, 1, 2]);
+            let positions: Vec<[f32; 3]> = stone.positions.iter().map(|p| p.position).collect();
+            assert_eq!(
+                positions,
+                vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+            );
+            assert!(stone.normals.iter().all(|n| n.normal == [0.0, 0.0, 1.0]));
+        }
+    }
+
+    #[test]
+    fn obj_polygons_become_fans_of_triangles() {
+        let stone = read_text(
+            "polygons.obj",
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv -1 0.5 0\nf 1 2 3 4\nf 1 4 5\n\
+             f 1/1 2/2 3/3 4/4 5/5\n",
+        );
+
+        assert_eq!(
+            stone.indices,
+            vec![0, 1, 2, 0, 2, 3, 0, 3, 4, 0, 1, 2, 0, 2, 3, 0, 3, 4]
+        );
+        assert_eq!(stone.positions.len(), 5);
+    }
+
+    #[test]
+    fn obj_without_normals_gets_them_from_its_faces() {
+        // one corner with a normal is not enough, the faces decide all of them
+        let stone = read_text(
+            "normals.obj",
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 1 0 0\nf 1 2 3//1\n",
+        );
+
+        assert_eq!(stone.normals.len(), 3);
+        for n in &stone.normals {
+            assert!((n.normal[2] - 1.0).abs() < 1e-6, "{:?}", n.normal);
+        }
+    }
+
+    #[test]
+    fn obj_indices_past_what_was_read_are_errors() {
+        for face in ["f 1 2 4", "f 0 1 2", "f 1 2 -4", "f 1 2"] {
+            let path = temporary("wrong.obj");
+            std::fs::write(&path, format!("v 0 0 0\nv 1 0 0\nv 0 1 0\n{}\n", face)).unwrap();
+            let read = read_stone(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert!(read.is_err(), "{}", face);
+        }
+    }
+
+    #[test]
+    fn ply_headers_may_end_lines_with_carriage_returns() {
+        let header = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\n\
+                      property float y\nproperty float z\nelement face 1\n\
+                      property list uchar int vertex_indices\nend_header\n";
+        let body = "0 0 0\n1 0 0\n0 1 0\n3 0 1 2\n";
+
+        let unix = read_text("unix.ply", &format!("{}{}", header, body));
+        let windows = read_text(
+            "windows.ply",
+            &format!("{}{}", header, body).replace('\n', "\r\n"),
+        );
+
+        for stone in [unix, windows] {
+            assert_eq!(stone.indices, vec![0, 1, 2]);
+            assert_eq!(stone.positions[1].position, [1.0, 0.0, 0.0]);
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::f32_3::{cross_f32_3, dd_f32_3, dot_product, sbtr_f32_3, vector_length};
+use crate::magma_ocean::Stone;
+use crate::positions::Position;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::AtomicBool;
+
+// checks a stone for what keeps it from being one closed surface with its normals outside,
+// vertices at the same place count as one so seams of split normals are no holes
+
+// whether petrify checks its stones, which it only does in debug builds
+pub static CHECK_STONES: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    // a count of indices not divisible by three
+    NotTriangles { indices: usize },
+    // not one normal per position
+    NormalCount { positions: usize, normals: usize },
+    IndexOutOfRange { triangle: usize, index: u32 },
+    // repeated corners or no area
+    DegenerateTriangle { triangle: usize },
+    // a hole, only one triangle on the edge
+    BoundaryEdge { edge: [u32; 2] },
+    NonManifoldEdge { edge: [u32; 2], triangles: usize },
+    // both triangles walk the edge the same way
+    InconsistentWinding { edge: [u32; 2] },
+    // against the triangles around it
+    InwardNormal { vertex: u32 },
+    // triangles clockwise seen from outside
+    InsideOut { volume: f32 },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+}
+
+impl Report {
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    pub fn count(&self, matches: fn(&Problem) -> bool) -> usize {
+        self.problems.iter().filter(|p| matches(p)).count()
+    }
+}
+
+impl Problem {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Problem::NotTriangles { .. } => "loose indices",
+            Problem::NormalCount { .. } => "wrong normal count",
+            Problem::IndexOutOfRange { .. } => "out of range indices",
+            Problem::DegenerateTriangle { .. } => "degenerate triangles",
+            Problem::BoundaryEdge { .. } => "boundary edges",
+            Problem::NonManifoldEdge { .. } => "non manifold edges",
+            Problem::InconsistentWinding { .. } => "inconsistently wound edges",
+            Problem::InwardNormal { .. } => "inward normals",
+            Problem::InsideOut { .. } => "inside out",
+        }
+    }
+}
+
+// how many problems of each kind, in the order they were found
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.problems.is_empty() {
+            return write!(f, "no problems");
+        }
+
+        let mut kinds: Vec<(&str, usize)> = vec![];
+        for p in &self.problems {
+            match kinds.iter_mut().find(|k| k.0 == p.kind()) {
+                Some(k) => k.1 += 1,
+                None => kinds.push((p.kind(), 1)),
+            }
+        }
+        let counts: Vec<String> = kinds.iter().map(|k| format!("{} {}", k.1, k.0)).collect();
+        write!(f, "{}", counts.join(", "))
+    }
+}
+
+pub fn validate(stone: &Stone) -> Report {
+    let mut problems = vec![];
+
+    if !stone.indices.len().is_multiple_of(3) {
+        problems.push(Problem::NotTriangles {
+            indices: stone.indices.len(),
+        });
+    }
+    if stone.normals.len() != stone.positions.len() {
+        problems.push(Problem::NormalCount {
+            positions: stone.positions.len(),
+            normals: stone.normals.len(),
+        });
+    }
+
+    let welded = welded(&stone.positions);
+
+    // every directed edge of the usable triangles, with how many triangles walk it
+    let mut edges: HashMap<[u32; 2], usize> = HashMap::new();
+    let mut face_sums = vec![[0.0; 3]; stone.positions.len()];
+    let mut volume = 0.0;
+
+    for (t, triangle) in stone.indices.chunks_exact(3).enumerate() {
+        if let Some(index) = triangle
+            .iter()
+            .find(|i| **i as usize >= stone.positions.len())
+        {
+            problems.push(Problem::IndexOutOfRange {
+                triangle: t,
+                index: *index,
+            });
+            continue;
+        }
+
+        let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| welded[i as usize]);
+        let [pa, pb, pc] = [a, b, c].map(|i| stone.positions[i as usize].position);
+        let face = cross_f32_3(sbtr_f32_3(pb, pa), sbtr_f32_3(pc, pa));
+        if a == b || b == c || c == a || vector_length(face) == 0.0 {
+            problems.push(Problem::DegenerateTriangle { triangle: t });
+            continue;
+        }
+
+        for i in triangle {
+            face_sums[*i as usize] = dd_f32_3(face_sums[*i as usize], face);
+        }
+        volume += dot_product(pa, cross_f32_3(pb, pc)) / 6.0;
+
+        for edge in [[a, b], [b, c], [c, a]] {
+            *edges.entry(edge).or_default() += 1;
+        }
+    }
+
+    let mut undirected: Vec<[u32; 2]> = edges
+        .keys()
+        .map(|e| [e[0].min(e[1]), e[0].max(e[1])])
+        .collect();
+    undirected.sort();
+    undirected.dedup();
+
+    for edge in undirected {
+        let forwards = *edges.get(&edge).unwrap_or(&0);
+        let backwards = *edges.get(&[edge[1], edge[0]]).unwrap_or(&0);
+        match forwards + backwards {
+            1 => problems.push(Problem::BoundaryEdge { edge }),
+            2 if forwards != 1 => problems.push(Problem::InconsistentWinding { edge }),
+            2 => {}
+            triangles => problems.push(Problem::NonManifoldEdge { edge, triangles }),
+        }
+    }
+
+    for (i, (n, sum)) in stone.normals.iter().zip(&face_sums).enumerate() {
+        if dot_product(n.normal, *sum) < 0.0 {
+            problems.push(Problem::InwardNormal { vertex: i as u32 });
+        }
+    }
+
+    if volume < 0.0 {
+        problems.push(Problem::InsideOut { volume });
+    }
+
+    Report { problems }
+}
+
+// for every vertex the first one at the same place, which stands for all the others there
+pub fn welded(positions: &[Position]) -> Vec<u32> {
+    let mut places: HashMap<[u32; 3], u32> = HashMap::new();
+    positions
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            *places
+                .entry(p.position.map(f32::to_bits))
+                .or_insert(i as u32)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::magma_ocean::{magma, petrify, petrify_flow};
+    use crate::shapes::random_profile;
+
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    // closed with every normal outside; the pointed ends of a stone from petrify weld into
+    // triangles without area, which do not open the surface but are bounded all the same
+    fn assert_closed(stone: &Stone, degenerate: usize, what: &str, seed: u64) {
+        let report = validate(stone);
+        let open = report.count(|p| {
+            !matches!(
+                p,
+                Problem::DegenerateTriangle { .. } | Problem::InwardNormal { .. }
+            )
+        });
+        let inward = report.count(|p| matches!(p, Problem::InwardNormal { .. }));
+        assert!(
+            !stone.indices.is_empty() && open == 0 && inward == 0,
+            "{} from seed {}: {}",
+            what,
+            seed,
+            report
+        );
+        assert!(
+            report.count(|p| matches!(p, Problem::DegenerateTriangle { .. })) <= degenerate,
+            "{} from seed {}: {}",
+            what,
+            seed,
+            report
+        );
+    }
+
+    #[test]
+    fn petrified_stones_are_closed_and_outward() {
+        for seed in 0..200 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let size = rng.gen_range(1.0..50.0);
+            let profile = random_profile(&mut rng);
+            let stone = petrify(magma(2, size, &mut rng), &profile, &mut rng);
+            // both ends are three points welded into one, with four triangles each
+            assert_closed(&stone, 8, "petrify", seed);
+        }
+    }
+
+    #[test]
+    fn flow_stones_are_closed_and_outward() {
+        for seed in 0..100 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let nodes = rng.gen_range(3..8);
+            let size = rng.gen_range(1.0..50.0);
+            let profile = random_profile(&mut rng);
+            let stone = petrify_flow(magma(nodes, size, &mut rng), &profile, &mut rng);
+            assert_closed(&stone, 0, "petrify_flow", seed);
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use crate::f32_3::{
+    cross_f32_3, dd_f32_3, dot_product, dstnc_f32_3, mltply_f32_3, nrmlz_f32_3, sbtr_f32_3,
+    vector_length,
+};
+use crate::magma_ocean::Stone;
+use crate::noise::{fractal, Displacement, Noise};
+use crate::positions::{Normal, Position};
+
+// surfaces where a field over space crosses zero, negative inside, sampled on a grid and
+// contoured with one vertex per cell the surface passes, so a whole cluster of points can
+// melt into one stone
+
+pub trait ScalarField {
+    fn value(&self, p: [f32; 3]) -> f32;
+}
+
+impl<F: Fn([f32; 3]) -> f32> ScalarField for F {
+    fn value(&self, p: [f32; 3]) -> f32 {
+        self(p)
+    }
+}
+
+// blobs around points that run together where they come close
+pub struct Metaballs {
+    pub centers: Vec<[f32; 3]>,
+    pub radius: f32,    // how far a ball reaches at all
+    pub threshold: f32, // the summed influence where the surface is, a lone ball is 1 at its center
+}
+
+impl ScalarField for Metaballs {
+    fn value(&self, p: [f32; 3]) -> f32 {
+        let mut influence = 0.0;
+        for c in &self.centers {
+            let d = dstnc_f32_3(*c, p) / self.radius;
+            if d < 1.0 {
+                influence += (1.0 - d * d).powi(3);
+            }
+        }
+        self.threshold - influence
+    }
+}
+
+pub fn metaballs(centers: Vec<[f32; 3]>, radius: f32) -> Metaballs {
+    Metaballs {
+        centers,
+        radius,
+        threshold: 0.2,
+    }
+}
+
+impl Metaballs {
+    // a box holding every ball
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let mut low = [f32::MAX; 3];
+        let mut high = [f32::MIN; 3];
+        for c in &self.centers {
+            for d in 0..3 {
+                low[d] = low[d].min(c[d] - self.radius);
+                high[d] = high[d].max(c[d] + self.radius);
+            }
+        }
+        (low, high)
+    }
+}
+
+// signed distances to simple solids
+pub enum Sdf {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+    Cuboid {
+        center: [f32; 3],
+        half: [f32; 3], // half the size along every axis
+    },
+    Capsule {
+        a: [f32; 3],
+        b: [f32; 3],
+        radius: f32,
+    },
+    // lying in the plane across y
+    Torus {
+        center: [f32; 3],
+        major: f32,
+        minor: f32,
+    },
+}
+
+impl ScalarField for Sdf {
+    fn value(&self, p: [f32; 3]) -> f32 {
+        match self {
+            Sdf::Sphere { center, radius } => dstnc_f32_3(*center, p) - radius,
+            Sdf::Cuboid { center, half } => {
+                let q = [0, 1, 2].map(|d| (p[d] - center[d]).abs() - half[d]);
+                let outside = vector_length(q.map(|x| x.max(0.0)));
+                outside + q[0].max(q[1]).max(q[2]).min(0.0)
+            }
+            Sdf::Capsule { a, b, radius } => {
+                let ab = sbtr_f32_3(*b, *a);
+                let t = (dot_product(sbtr_f32_3(p, *a), ab) / dot_product(ab, ab)).clamp(0.0, 1.0);
+                dstnc_f32_3(dd_f32_3(*a, mltply_f32_3(ab, t)), p) - radius
+            }
+            Sdf::Torus {
+                center,
+                major,
+                minor,
+            } => {
+                let q = sbtr_f32_3(p, *center);
+                let ring = (q[0] * q[0] + q[2] * q[2]).sqrt() - major;
+                (ring * ring + q[1] * q[1]).sqrt() - minor
+            }
+        }
+    }
+}
+
+// both fields, filleted where they meet over about k
+pub struct SmoothUnion<'a> {
+    pub a: &'a dyn ScalarField,
+    pub b: &'a dyn ScalarField,
+    pub k: f32,
+}
+
+impl ScalarField for SmoothUnion<'_> {
+    fn value(&self, p: [f32; 3]) -> f32 {
+        let (a, b) = (self.a.value(p), self.b.value(p));
+        let h = (0.5 + 0.5 * (b - a) / self.k).clamp(0.0, 1.0);
+        b + (a - b) * h - self.k * h * (1.0 - h)
+    }
+}
+
+// a with b cut away
+pub struct Difference<'a> {
+    pub a: &'a dyn ScalarField,
+    pub b: &'a dyn ScalarField,
+}
+
+impl ScalarField for Difference<'_> {
+    fn value(&self, p: [f32; 3]) -> f32 {
+        self.a.value(p).max(-self.b.value(p))
+    }
+}
+
+// a field with its surface pushed out and in by noise
+pub struct Displaced<'a> {
+    pub field: &'a dyn ScalarField,
+    pub noise: &'a Noise,
+    pub displacement: Displacement,
+}
+
+impl ScalarField for Displaced<'_> {
+    fn value(&self, p: [f32; 3]) -> f32 {
+        self.field.value(p) - fractal(self.noise, &self.displacement, p)
+    }
+}
+
+// pointing out of the surface, by central differences h apart
+pub fn gradient<F: ScalarField + ?Sized>(field: &F, p: [f32; 3], h: f32) -> [f32; 3] {
+    [0, 1, 2].map(|d| {
+        let mut a = p;
+        let mut b = p;
+        a[d] += h;
+        b[d] -= h;
+        (field.value(a) - field.value(b)) / (2.0 * h)
+    })
+}
+
+// how much the vertex of a cell is pulled towards the middle of its crossings, so it stays
+// put along flat or straight stretches of surface where the planes leave it free
+static MASS_PULL: f32 = 0.05;
+
+// the surface of the field between low and high, on a grid of cells about cell wide;
+// the field should be positive all along the edges of the box or the surface is left open there
+// and cells the surface passes more than once, with features smaller than a cell, may join
+// four triangles along an edge
+pub fn dual_contour<F: ScalarField + ?Sized>(
+    field: &F,
+    low: [f32; 3],
+    high: [f32; 3],
+    cell: f32,
+) -> Stone {
+    let n = [0, 1, 2].map(|d| (((high[d] - low[d]) / cell).ceil() as usize).max(1) + 1);
+    let point = |i: [usize; 3]| [0, 1, 2].map(|d| low[d] + i[d] as f32 * cell);
+    let index = |i: [usize; 3]| i[0] + n[0] * (i[1] + n[1] * i[2]);
+
+    let mut values = vec![0.0; n[0] * n[1] * n[2]];
+    for z in 0..n[2] {
+        for y in 0..n[1] {
+            for x in 0..n[0] {
+                values[index([x, y, z])] = field.value(point([x, y, z]));
+            }
+        }
+    }
+    let inside = |i: [usize; 3]| values[index(i)] < 0.0;
+
+    let mut stone = Stone {
+        positions: vec![],
+        normals: vec![],
+        indices: vec![],
+    };
+
+    // one vertex for every cell with corners on both sides, where the planes through its
+    // crossings meet best
+    let cells = n.map(|c| c - 1);
+    let mut vertex = vec![u32::MAX; cells[0] * cells[1] * cells[2]];
+    let cell_index = |i: [usize; 3]| i[0] + cells[0] * (i[1] + cells[1] * i[2]);
+    for z in 0..cells[2] {
+        for y in 0..cells[1] {
+            for x in 0..cells[0] {
+                let mut crossings = vec![];
+                for a in 0..3 {
+                    for corner in 0..4 {
+                        let mut from = [x, y, z];
+                        from[(a + 1) % 3] += corner & 1;
+                        from[(a + 2) % 3] += corner >> 1;
+                        let mut to = from;
+                        to[a] += 1;
+                        if inside(from) == inside(to) {
+                            continue;
+                        }
+                        let (vf, vt) = (values[index(from)], values[index(to)]);
+                        let t = vf / (vf - vt);
+                        let p = dd_f32_3(
+                            point(from),
+                            mltply_f32_3(sbtr_f32_3(point(to), point(from)), t),
+                        );
+                        crossings.push((p, nrmlz_f32_3(gradient(field, p, cell / 8.0))));
+                    }
+                }
+                if crossings.is_empty() {
+                    continue;
+                }
+
+                let corner = point([x, y, z]);
+                let p = cell_vertex(&crossings).unwrap_or(corner);
+                let p = [0, 1, 2].map(|d| p[d].clamp(corner[d], corner[d] + cell));
+                vertex[cell_index([x, y, z])] = stone.positions.len() as u32;
+                stone.positions.push(Position { position: p });
+                stone.normals.push(Normal {
+                    normal: nrmlz_f32_3(gradient(field, p, cell / 8.0)),
+                });
+            }
+        }
+    }
+
+    // a quad across every grid edge the surface crosses, between the four cells around it,
+    // turned so it faces the outside end of the edge
+    for z in 0..n[2] {
+        for y in 0..n[1] {
+            for x in 0..n[0] {
+                let from = [x, y, z];
+                for a in 0..3 {
+                    let (b, c) = ((a + 1) % 3, (a + 2) % 3);
+                    if from[a] + 1 >= n[a] || from[b] == 0 || from[c] == 0 {
+                        continue;
+                    }
+                    if from[b] >= cells[b] || from[c] >= cells[c] {
+                        continue;
+                    }
+                    let mut to = from;
+                    to[a] += 1;
+                    if inside(from) == inside(to) {
+                        continue;
+                    }
+
+                    // counterclockwise seen from the end of the edge
+                    let mut quad = [[0, 0], [1, 0], [1, 1], [0, 1]].map(|[db, dc]| {
+                        let mut i = from;
+                        i[b] = i[b] + db - 1;
+                        i[c] = i[c] + dc - 1;
+                        vertex[cell_index(i)]
+                    });
+                    if !inside(from) {
+                        quad.reverse();
+                    }
+
+                    // cut along the shorter diagonal
+                    let at = |v: u32| stone.positions[v as usize].position;
+                    let [q0, q1, q2, q3] = quad;
+                    if dstnc_f32_3(at(q0), at(q2)) <= dstnc_f32_3(at(q1), at(q3)) {
+                        stone.indices.extend([q0, q1, q2, q0, q2, q3]);
+                    } else {
+                        stone.indices.extend([q0, q1, q3, q1, q2, q3]);
+                    }
+                }
+            }
+        }
+    }
+
+    stone
+}
+
+// the point closest to all the planes through the crossings, each given by a point and its
+// normal, held near the middle of the crossings where they leave it free
+fn cell_vertex(crossings: &[([f32; 3], [f32; 3])]) -> Option<[f32; 3]> {
+    let middle = mltply_f32_3(
+        crossings
+            .iter()
+            .fold([0.0; 3], |sum, (p, _)| dd_f32_3(sum, *p)),
+        1.0 / crossings.len() as f32,
+    );
+
+    // the normal equations, worked out around the middle so the numbers stay small
+    let mut m = [[0.0; 3]; 3];
+    let mut rhs = [0.0; 3];
+    for (p, normal) in crossings {
+        let offset = dot_product(*normal, sbtr_f32_3(*p, middle));
+        for r in 0..3 {
+            for c in 0..3 {
+                m[r][c] += normal[r] * normal[c];
+            }
+            rhs[r] += normal[r] * offset;
+        }
+    }
+    for (d, row) in m.iter_mut().enumerate() {
+        row[d] += MASS_PULL;
+    }
+
+    // cramer's rule, the matrix being symmetric its rows stand in for its columns
+    let det = dot_product(m[0], cross_f32_3(m[1], m[2]));
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let x = [
+        dot_product(rhs, cross_f32_3(m[1], m[2])),
+        dot_product(m[0], cross_f32_3(rhs, m[2])),
+        dot_product(m[0], cross_f32_3(m[1], rhs)),
+    ];
+    Some(dd_f32_3(middle, mltply_f32_3(x, 1.0 / det)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::mass_properties;
+    use crate::validation::validate;
+
+    use std::f64::consts::PI;
+
+    fn assert_closed(stone: &Stone) {
+        let report = validate(stone);
+        assert!(!stone.indices.is_empty() && report.is_valid(), "{}", report);
+    }
+
+    fn relative(measured: f64, expected: f64) -> f64 {
+        (measured - expected).abs() / expected
+    }
+
+    #[test]
+    fn a_sphere_contours_into_a_closed_ball() {
+        let sphere = Sdf::Sphere {
+            center: [0.2, -0.1, 0.3],
+            radius: 1.0,
+        };
+        let stone = dual_contour(&sphere, [-1.5; 3], [1.5; 3], 0.1);
+        assert_closed(&stone);
+
+        let volume = mass_properties(&stone).volume;
+        assert!(relative(volume, 4.0 / 3.0 * PI) < 0.02, "{}", volume);
+    }
+
+    #[test]
+    fn two_metaballs_melt_into_one_closed_stone() {
+        let balls = metaballs(vec![[-0.5, 0.0, 0.0], [0.5, 0.0, 0.0]], 1.0);
+        let (low, high) = balls.bounds();
+        let stone = dual_contour(&balls, low, high, 0.1);
+        assert_closed(&stone);
+    }
+
+    #[test]
+    fn apart_metaballs_are_two_balls_of_the_threshold_radius() {
+        let balls = metaballs(vec![[-2.0, 0.0, 0.0], [2.0, 0.0, 0.0]], 1.0);
+        let (low, high) = balls.bounds();
+        let stone = dual_contour(&balls, low, high, 0.05);
+        assert_closed(&stone);
+
+        // where a lone ball's influence falls to the threshold
+        let radius = (1.0 - (balls.threshold as f64).cbrt()).sqrt();
+        let volume = mass_properties(&stone).volume;
+        assert!(
+            relative(volume, 2.0 * 4.0 / 3.0 * PI * radius.powi(3)) < 0.03,
+            "{}",
+            volume
+        );
+    }
+}